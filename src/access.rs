@@ -0,0 +1,92 @@
+//! The lookup, creation and storing shared by the methods of GetCached which
+//! create plugins, so that each applies the frozen, poisoned and capacity
+//! state of its extensible type, and records its stats.
+//!
+//! Each function takes the plugin P whose create is called, which is what
+//! stats and the observer are told about, and the key K its value is stored
+//! under, which is what eviction tracks. They differ for the methods which
+//! store a value of P under a private key, such as get_rc.
+
+use {Extensible, Key};
+use {hooks, lru, versioned};
+
+/// What an access found stored under its key.
+pub enum Lookup {
+    /// A fresh value is stored, and the access was recorded.
+    Fresh,
+    /// No fresh value is stored, and one may be created.
+    Missing,
+    /// No value may be returned or created, as the extensible type is
+    /// poisoned, or is frozen and no fresh value is stored.
+    Unavailable
+}
+
+/// Looks up the value stored under K for P, reading the state of
+/// extensible once.
+pub fn lookup<P: Key, K: Key, E: Extensible>(extensible: &mut E) -> Lookup {
+    let flags = hooks::flags(extensible.extensions());
+    if flags.poisoned {
+        return Lookup::Unavailable;
+    }
+    if versioned::is_fresh::<K, E>(extensible) {
+        hooks::hit::<P, E>(extensible);
+        if flags.bounded {
+            lru::touch::<K, E>(extensible);
+        }
+        return Lookup::Fresh;
+    }
+    if flags.frozen { Lookup::Unavailable } else { Lookup::Missing }
+}
+
+/// Returns true if a fresh value is stored under K, recording the access as
+/// lookup does, but whether or not extensible is frozen or poisoned.
+pub fn found<P: Key, K: Key, E: Extensible>(extensible: &mut E) -> bool {
+    if !versioned::is_fresh::<K, E>(extensible) {
+        return false;
+    }
+    hooks::hit::<P, E>(extensible);
+    touch::<K, E>(extensible);
+    true
+}
+
+/// Calls create, which creates a value of P, recording the miss and, when
+/// profiling, how long it took.
+pub fn call<P: Key, E: Extensible, V, F: FnOnce(&mut E) -> V>(extensible: &mut E, create: F) -> V {
+    hooks::miss::<P, E>(extensible);
+    let start = hooks::start();
+    let created = create(extensible);
+    hooks::finish::<P, E>(extensible, start);
+    created
+}
+
+/// Stores value under K as a value created for P, telling the observer and
+/// tracking it for eviction.
+pub fn store<P: Key, K: Key, E: Extensible>(extensible: &mut E, value: K::Value) {
+    versioned::insert::<K, E>(extensible, value);
+    hooks::created::<P, E>(extensible);
+    touch::<K, E>(extensible);
+}
+
+/// Records an access of K for eviction, if a capacity is set.
+pub fn touch<K: Key, E: Extensible>(extensible: &mut E) {
+    if hooks::flags(extensible.extensions()).bounded {
+        lru::touch::<K, E>(extensible);
+    }
+}
+
+/// Looks up the value stored under K for P, calling create and storing its
+/// value if none is. Returns true if a fresh value is stored afterwards.
+pub fn create_and_store<P, K, E, F>(extensible: &mut E, create: F) -> bool
+where P: Key, K: Key, E: Extensible, F: FnOnce(&mut E) -> Option<K::Value> {
+    match lookup::<P, K, E>(extensible) {
+        Lookup::Fresh => true,
+        Lookup::Unavailable => false,
+        Lookup::Missing => match call::<P, E, _, _>(extensible, create) {
+            Some(value) => {
+                store::<P, K, E>(extensible, value);
+                true
+            },
+            None => false
+        }
+    }
+}
@@ -0,0 +1,13 @@
+//! Plugins whose values are boxed trait objects.
+
+use Key;
+
+/// The key of a plugin whose value is a `Box<U>`, where U is usually a trait,
+/// for plugins whose value is a behaviour rather than concrete data.
+///
+/// Implement PluginFor for `BoxedPlugin<Trait + 'static>`, creating the box,
+/// and get a reference to the trait object through get_ref_boxed. Only one
+/// plugin can be stored for each trait this way.
+pub struct BoxedPlugin<Sized? U>;
+
+impl<Sized? U: 'static> Key for BoxedPlugin<U> { type Value = Box<U>; }
@@ -0,0 +1,39 @@
+//! Categories of plugins, for counting the stored plugins of each.
+//!
+//! Categories can be of any type, so each registered category is boxed, and
+//! compared with the category being counted only if it is of the same type.
+
+use std::any::{Any, AnyRefExt};
+use std::collections::HashMap;
+use std::intrinsics::TypeId;
+use anymap::AnyMap;
+use Key;
+use raw;
+
+/// The key under which the categories of registered plugins are stored.
+pub struct CategoriesKey;
+
+impl Key for CategoriesKey { type Value = HashMap<TypeId, Box<Any + 'static>>; }
+
+bookkeeping!(CategoriesKey)
+
+/// Registers category in map as the category of the plugin whose key has the
+/// TypeId id, in place of any other.
+pub fn register<C: 'static>(map: &mut AnyMap, id: TypeId, category: C) {
+    raw::get_or_insert_with::<CategoriesKey>(map, HashMap::new)
+        .insert(id, box category as Box<Any + 'static>);
+}
+
+/// Returns the number of ids whose plugins are registered in map with a
+/// category equal to category.
+pub fn count<C: PartialEq + 'static, I: Iterator<TypeId>>(map: &AnyMap, ids: I, category: &C)
+        -> uint {
+    let categories = match raw::find::<CategoriesKey>(map) {
+        Some(categories) => categories,
+        None => return 0
+    };
+    ids.filter(|id| match categories.get(id) {
+        Some(registered) => registered.downcast_ref::<C>() == Some(category),
+        None => false
+    }).count()
+}
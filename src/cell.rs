@@ -0,0 +1,136 @@
+//! Plugins for extensible types whose extension storage is behind a RefCell,
+//! so they can be cached through a shared reference.
+
+use std::cell::{Ref, RefCell};
+use anymap::AnyMap;
+use {Key, PluginFor};
+use raw;
+
+/// Defines an interface that extensible types with interior mutability must
+/// implement.
+pub trait CellExtensible {
+    /// Get a reference to the cell holding the type's extension storage.
+    fn extensions_cell(&self) -> &RefCell<AnyMap>;
+}
+
+/// Expose an interface for cacheing plugins through a shared reference.
+pub trait GetShared: CellExtensible {
+    /// Creates, stores and returns an instance of T's value if construction
+    /// through T's implementation of create succeeds, otherwise None.
+    ///
+    /// The cell is not borrowed while create runs, so create may itself get
+    /// other plugins. If a value is stored under T while create runs, that
+    /// value is kept and returned.
+    fn get_ref_shared<T: PluginFor<Self>>(&self) -> Option<T::Value> where T::Value: Clone {
+        match raw::find::<T>(&*self.extensions_cell().borrow()) {
+            Some(t) => return Some(t.clone()),
+            None => ()
+        }
+        let t = try_option!(<T as PluginFor<Self>>::create(self));
+        let mut map = self.extensions_cell().borrow_mut();
+        if !raw::contains::<T>(&*map) {
+            raw::insert::<T>(&mut *map, t);
+        }
+        raw::find::<T>(&*map).map(|t| t.clone())
+    }
+
+    /// Creates and stores T's value as get_ref_shared does, and returns a
+    /// guard which derefs to it, without cloning it, or None if construction
+    /// fails.
+    ///
+    /// The guard keeps the cell borrowed while it lives, so no plugin can be
+    /// stored through the cell until it is dropped: getting a plugin which
+    /// is not stored while holding one panics.
+    fn get_ref_cell<T: PluginFor<Self>>(&self) -> Option<PluginRef<T>> {
+        if !self.contains_shared::<T>() {
+            let t = try_option!(<T as PluginFor<Self>>::create(self));
+            let mut map = self.extensions_cell().borrow_mut();
+            if !raw::contains::<T>(&*map) {
+                raw::insert::<T>(&mut *map, t);
+            }
+        }
+        Some(PluginRef { map: self.extensions_cell().borrow() })
+    }
+
+    /// Returns true if a value is currently stored under T.
+    fn contains_shared<T: Key>(&self) -> bool {
+        raw::contains::<T>(&*self.extensions_cell().borrow())
+    }
+}
+
+impl<T: CellExtensible> GetShared for T {}
+
+/// A guard for the value stored under K in a cell, as returned by
+/// get_ref_cell, which keeps the cell borrowed while it lives.
+pub struct PluginRef<'a, K> {
+    map: Ref<'a, AnyMap>
+}
+
+impl<'a, K: Key> Deref<K::Value> for PluginRef<'a, K> {
+    fn deref<'b>(&'b self) -> &'b K::Value {
+        raw::find::<K>(&*self.map).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use anymap::AnyMap;
+    use {Key, PluginFor};
+    use super::{CellExtensible, GetShared};
+
+    struct Shared {
+        map: RefCell<AnyMap>
+    }
+
+    impl CellExtensible for Shared {
+        fn extensions_cell(&self) -> &RefCell<AnyMap> { &self.map }
+    }
+
+    #[deriving(PartialEq, Show, Clone)]
+    struct One(uint);
+
+    impl Key for One { type Value = One; }
+
+    impl PluginFor<Shared> for One {
+        fn create(_: &Shared) -> Option<One> { Some(One(1)) }
+    }
+
+    #[deriving(PartialEq, Show, Clone)]
+    struct Two(uint);
+
+    impl Key for Two { type Value = Two; }
+
+    // Gets another plugin during creation.
+    impl PluginFor<Shared> for Two {
+        fn create(shared: &Shared) -> Option<Two> {
+            shared.get_ref_shared::<One>().map(|One(n)| Two(n * 2))
+        }
+    }
+
+    #[test] fn test_get_ref_shared() {
+        let shared = Shared { map: RefCell::new(AnyMap::new()) };
+        let shared = &shared;
+        assert_eq!(shared.get_ref_shared::<Two>(), Some(Two(2)))
+        assert!(shared.contains_shared::<One>())
+        assert!(shared.contains_shared::<Two>())
+        assert_eq!(shared.get_ref_shared::<One>(), Some(One(1)))
+    }
+
+    #[test] fn test_get_ref_cell() {
+        let shared = Shared { map: RefCell::new(AnyMap::new()) };
+        {
+            let two = shared.get_ref_cell::<Two>().unwrap();
+            assert_eq!(*two, Two(2))
+            assert_eq!(*shared.get_ref_cell::<One>().unwrap(), One(1))
+        }
+        *shared.map.borrow_mut().find_mut::<Two>().unwrap() = Two(5);
+        assert_eq!(*shared.get_ref_cell::<Two>().unwrap(), Two(5))
+    }
+
+    #[test] #[should_fail] fn test_get_ref_cell_conflict() {
+        let shared = Shared { map: RefCell::new(AnyMap::new()) };
+        let _one = shared.get_ref_cell::<One>().unwrap();
+        shared.get_ref_cell::<Two>();
+    }
+}
@@ -0,0 +1,49 @@
+//! Copying of stored plugins between extensible types.
+//!
+//! Values are type-erased in an AnyMap, so each plugin which can be copied
+//! must be registered on the extensible type it is copied from.
+
+use std::intrinsics::TypeId;
+use anymap::AnyMap;
+use Key;
+use raw;
+
+/// The key under which the plugins registered for copying are stored.
+pub struct ClonesKey;
+
+impl Key for ClonesKey { type Value = Vec<Cloner>; }
+
+bookkeeping!(ClonesKey)
+
+/// A plugin registered for copying.
+#[deriving(Clone)]
+pub struct Cloner {
+    /// The TypeId of the plugin's key.
+    pub id: TypeId,
+    /// Stores a clone of the plugin's value from one map in another,
+    /// returning false if it is not stored.
+    pub clone: fn(&AnyMap, &mut AnyMap) -> bool
+}
+
+/// Returns the registration of K.
+pub fn cloner<K: Key>() -> Cloner where K::Value: Clone {
+    Cloner { id: TypeId::of::<K>(), clone: clone::<K> }
+}
+
+/// Registers cloner in map, unless a plugin with its TypeId is registered.
+pub fn register(map: &mut AnyMap, cloner: Cloner) {
+    let registered = raw::get_or_insert_with::<ClonesKey>(map, Vec::new);
+    if !registered.iter().any(|c| c.id == cloner.id) {
+        registered.push(cloner);
+    }
+}
+
+fn clone<K: Key>(from: &AnyMap, to: &mut AnyMap) -> bool where K::Value: Clone {
+    match raw::find::<K>(from) {
+        Some(value) => {
+            raw::insert::<K>(to, value.clone());
+            true
+        },
+        None => false
+    }
+}
@@ -0,0 +1,34 @@
+//! The context in which plugins which store other plugins are created.
+
+use {Extensible, Key};
+use raw;
+use versioned;
+
+/// The context passed to PluginForCtx::create_with_ctx, through which the
+/// plugin's source can be read and other plugins stored.
+pub struct PluginContext<'a, E: 'a> {
+    extensible: &'a mut E
+}
+
+/// Returns the context for creating a plugin from extensible.
+pub fn new<'a, E: Extensible>(extensible: &'a mut E) -> PluginContext<'a, E> {
+    PluginContext { extensible: extensible }
+}
+
+impl<'a, E: Extensible> PluginContext<'a, E> {
+    /// Returns the source the plugin is created from.
+    pub fn source(&self) -> &E {
+        &*self.extensible
+    }
+
+    /// Stores value under K at once, as GetCached::insert does, returning
+    /// the value previously stored under K.
+    pub fn insert<K: Key>(&mut self, value: K::Value) -> Option<K::Value> {
+        versioned::insert::<K, E>(self.extensible, value)
+    }
+
+    /// Returns true if a value is currently stored under K.
+    pub fn contains<K: Key>(&self) -> bool {
+        raw::contains::<K>(self.extensible.extensions())
+    }
+}
@@ -0,0 +1,59 @@
+//! Plugin values which are either borrowed from the cache or owned.
+
+/// The value of a plugin as returned by get_cow: borrowed from the stored
+/// plugins when the value was stored, or owned when it was not, as with the
+/// no-cache feature.
+///
+/// A borrowed value holds the borrow of the extensible type taken by get_cow
+/// for 'a, so no other plugin can be got until it is dropped or turned into
+/// an owned value by into_owned. It derefs to the value either way.
+pub enum PluginCow<'a, T: 'a> {
+    /// A value borrowed from the stored plugins.
+    Borrowed(&'a T),
+    /// A value which was not stored.
+    Owned(T)
+}
+
+impl<'a, T: Clone> PluginCow<'a, T> {
+    /// Returns the owned value, cloning it if it is borrowed.
+    pub fn into_owned(self) -> T {
+        match self {
+            PluginCow::Borrowed(value) => value.clone(),
+            PluginCow::Owned(value) => value
+        }
+    }
+
+    /// Returns true if the value is borrowed from the stored plugins.
+    pub fn is_borrowed(&self) -> bool {
+        match *self {
+            PluginCow::Borrowed(_) => true,
+            PluginCow::Owned(_) => false
+        }
+    }
+}
+
+impl<'a, T> Deref<T> for PluginCow<'a, T> {
+    fn deref<'b>(&'b self) -> &'b T {
+        match *self {
+            PluginCow::Borrowed(value) => value,
+            PluginCow::Owned(ref value) => value
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PluginCow;
+
+    #[test] fn test_into_owned() {
+        let value = "borrowed".to_string();
+        let borrowed = PluginCow::Borrowed(&value);
+        assert!(borrowed.is_borrowed())
+        assert_eq!(borrowed.as_slice(), "borrowed")
+        assert_eq!(borrowed.into_owned(), value)
+
+        let owned: PluginCow<String> = PluginCow::Owned("owned".to_string());
+        assert!(!owned.is_borrowed())
+        assert_eq!(owned.into_owned(), "owned".to_string())
+    }
+}
@@ -0,0 +1,82 @@
+//! A printable list of the plugins stored in an extensible type.
+
+use std::fmt;
+use std::intrinsics::TypeId;
+use anymap::AnyMap;
+use names;
+
+/// The TypeIds of the keys of the plugins stored in an extensible type, as
+/// returned by debug_plugins, with the names of their types.
+///
+/// Values are type-erased, so they can't be shown. Each key is shown by its
+/// type name, which is known once a value was stored under it through its
+/// type, and otherwise by the hash of its TypeId. They are in order of hash.
+pub struct PluginDebug {
+    ids: Vec<TypeId>,
+    names: Vec<Option<&'static str>>
+}
+
+impl PluginDebug {
+    /// Returns the TypeIds, in order of hash.
+    pub fn ids(&self) -> &[TypeId] {
+        self.ids.as_slice()
+    }
+
+    /// Returns the type names of the keys, in the order of ids, or None for
+    /// those whose names are not known.
+    pub fn names(&self) -> &[Option<&'static str>] {
+        self.names.as_slice()
+    }
+}
+
+/// Returns the debug representation of ids, with their names in map.
+pub fn new<I: Iterator<TypeId>>(ids: I, map: &AnyMap) -> PluginDebug {
+    let mut ids: Vec<TypeId> = ids.collect();
+    ids.sort_by(|a, b| a.hash().cmp(&b.hash()));
+    let names = ids.iter().map(|id| names::find(map, id)).collect();
+    PluginDebug { ids: ids, names: names }
+}
+
+impl fmt::Show for PluginDebug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "PluginDebug {{ "));
+        for (i, (id, name)) in self.ids.iter().zip(self.names.iter()).enumerate() {
+            if i > 0 {
+                try!(write!(f, ", "));
+            }
+            match *name {
+                Some(name) => try!(write!(f, "{}", name)),
+                None => try!(write!(f, "{:x}", id.hash()))
+            }
+        }
+        write!(f, " }}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::intrinsics::TypeId;
+    use anymap::AnyMap;
+    use Key;
+    use names;
+
+    struct Named;
+
+    impl Key for Named { type Value = (); }
+
+    #[test] fn test_show() {
+        let mut map = AnyMap::new();
+        let empty = super::new(Vec::new().into_iter(), &map);
+        assert_eq!(format!("{}", empty), "PluginDebug {  }".to_string())
+
+        names::record::<Named>(&mut map);
+        let unnamed = TypeId::of::<uint>();
+        let debug = super::new(vec![unnamed, TypeId::of::<Named>()].into_iter(), &map);
+        let (a, b) = (debug.ids()[0].hash(), debug.ids()[1].hash());
+        assert!(a <= b)
+        assert_eq!(debug.names().iter().filter(|name| name.is_some()).count(), 1)
+        let shown = format!("{}", debug);
+        assert!(shown.as_slice().contains("Named"))
+        assert!(shown.as_slice().contains(format!("{:x}", unnamed.hash()).as_slice()))
+    }
+}
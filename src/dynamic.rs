@@ -0,0 +1,74 @@
+//! Creation of plugins chosen at runtime by the TypeId of their key.
+//!
+//! Whether a type is a plugin for an extensible type is only known at compile
+//! time, so plugins which can be created dynamically must be registered on
+//! the extensible type. Values created dynamically are stored under the
+//! TypeId of their key, as those created statically are, so either can find
+//! the other's values.
+
+use std::any::{Any, AnyRefExt};
+use std::collections::HashMap;
+use std::intrinsics::TypeId;
+use anymap::AnyMap;
+use {Extensible, GetCached, Key, PluginFor};
+use hooks;
+use raw;
+use versioned;
+
+/// The key under which the creators of registered plugins are stored, each
+/// boxed as a Creator of the extensible type.
+pub struct CreatorsKey;
+
+impl Key for CreatorsKey { type Value = HashMap<TypeId, Box<Any + 'static>>; }
+
+bookkeeping!(CreatorsKey)
+
+/// Creates the value of a registered plugin for E.
+pub enum Creator<E> {
+    /// Creates and stores the value of a plugin, returning true if a value is
+    /// stored afterwards.
+    Static(fn(&mut E) -> bool),
+    /// Creates a type-erased value, which must be of the plugin's value type.
+    Dynamic(fn(&E) -> Option<Box<Any + 'static>>)
+}
+
+/// Returns the creator of K for E.
+pub fn creator<K: PluginFor<E>, E: Extensible>() -> Creator<E> {
+    Creator::Static(precompute::<K, E>)
+}
+
+/// Registers creator in map as the creator of the plugin whose key has the
+/// TypeId id, in place of any other.
+pub fn register<E: 'static>(map: &mut AnyMap, id: TypeId, creator: Creator<E>) {
+    raw::get_or_insert_with::<CreatorsKey>(map, HashMap::new)
+        .insert(id, box creator as Box<Any + 'static>);
+}
+
+/// Creates and stores the value of the registered plugin whose key has the
+/// TypeId id, returning false if construction fails or no such plugin is
+/// registered.
+pub fn create<E: Extensible + 'static>(extensible: &mut E, id: TypeId) -> bool {
+    let creator = match raw::find::<CreatorsKey>(extensible.extensions())
+            .and_then(|creators| creators.get(&id))
+            .and_then(|creator| creator.downcast_ref::<Creator<E>>()) {
+        Some(&Creator::Static(create)) => Creator::Static(create),
+        Some(&Creator::Dynamic(create)) => Creator::Dynamic(create),
+        None => return false
+    };
+    match creator {
+        Creator::Static(create) => create(extensible),
+        Creator::Dynamic(create) => match create(&*extensible) {
+            Some(value) => {
+                versioned::record(extensible, id);
+                extensible.extensions_mut().as_raw_mut().insert(id, value);
+                hooks::created_id(&*extensible, id);
+                true
+            },
+            None => false
+        }
+    }
+}
+
+fn precompute<K: PluginFor<E>, E: Extensible>(extensible: &mut E) -> bool {
+    extensible.precompute::<K>()
+}
@@ -0,0 +1,75 @@
+//! Entries for plugins which may or may not be stored, as returned by
+//! plugin_entry.
+
+use std::intrinsics::TypeId;
+use {Extensible, Key};
+use hooks;
+use raw;
+use versioned;
+
+/// The entry of a plugin, which holds the mutable borrow of its extensible
+/// type while it lives.
+pub enum PluginEntry<'a, E: 'a, K> {
+    /// A fresh value is stored under K.
+    Occupied(OccupiedEntry<'a, E, K>),
+    /// No fresh value is stored under K.
+    Vacant(VacantEntry<'a, E, K>)
+}
+
+/// The entry of a plugin whose value is stored.
+pub struct OccupiedEntry<'a, E: 'a, K> {
+    extensible: &'a mut E
+}
+
+/// The entry of a plugin whose value is not stored, or is stale.
+pub struct VacantEntry<'a, E: 'a, K> {
+    extensible: &'a mut E
+}
+
+/// Returns the entry of K in extensible.
+pub fn new<'a, K: Key, E: Extensible>(extensible: &'a mut E) -> PluginEntry<'a, E, K> {
+    if versioned::is_fresh::<K, E>(extensible) {
+        PluginEntry::Occupied(OccupiedEntry { extensible: extensible })
+    } else {
+        PluginEntry::Vacant(VacantEntry { extensible: extensible })
+    }
+}
+
+impl<'a, E: Extensible, K: Key> OccupiedEntry<'a, E, K> {
+    /// Returns a reference of the stored value.
+    pub fn get(&self) -> &K::Value {
+        raw::find::<K>(self.extensible.extensions()).unwrap()
+    }
+
+    /// Returns a mutable ref of the stored value.
+    pub fn get_mut(&mut self) -> &mut K::Value {
+        raw::find_mut::<K>(self.extensible.extensions_mut()).unwrap()
+    }
+
+    /// Returns a mutable ref of the stored value which lives as long as the
+    /// borrow of the extensible type.
+    pub fn into_mut(self) -> &'a mut K::Value {
+        raw::find_mut::<K>(self.extensible.extensions_mut()).unwrap()
+    }
+
+    /// Stores value in place of the stored value, returning it.
+    pub fn insert(&mut self, value: K::Value) -> K::Value {
+        versioned::insert::<K, E>(self.extensible, value).unwrap()
+    }
+
+    /// Removes and returns the stored value, telling the observer as remove
+    /// does.
+    pub fn remove(self) -> K::Value {
+        let value = raw::remove::<K>(self.extensible.extensions_mut()).unwrap();
+        hooks::removed(self.extensible.extensions(), TypeId::of::<K>());
+        value
+    }
+}
+
+impl<'a, E: Extensible, K: Key> VacantEntry<'a, E, K> {
+    /// Stores value, as insert does, returning a mutable ref of it.
+    pub fn insert(self, value: K::Value) -> &'a mut K::Value {
+        versioned::insert::<K, E>(self.extensible, value);
+        raw::find_mut::<K>(self.extensible.extensions_mut()).unwrap()
+    }
+}
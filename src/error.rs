@@ -0,0 +1,70 @@
+//! Reasons a plugin could not be created or got.
+
+use std::error::Error;
+use std::fmt;
+use std::intrinsics::TypeId;
+
+/// Why a plugin could not be created.
+///
+/// get_ref_err returns NotAvailable when create returns None. Plugins which
+/// implement PluginForResult with this as their error can also report the
+/// other variants.
+pub enum PluginError {
+    /// The plugin can't be created from this source.
+    NotAvailable,
+    /// The plugin depends on the plugin with this key TypeId, which could not
+    /// be created.
+    DependencyMissing(TypeId),
+    /// Some other error occurred.
+    Custom(Box<Error + Send>)
+}
+
+impl Error for PluginError {
+    fn description(&self) -> &str {
+        match *self {
+            PluginError::NotAvailable => "plugin not available",
+            PluginError::DependencyMissing(_) => "plugin dependency missing",
+            PluginError::Custom(ref err) => err.description()
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            PluginError::Custom(ref err) => Some(&**err as &Error),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Show for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PluginError::DependencyMissing(id) =>
+                write!(f, "plugin dependency missing: {}", id.hash()),
+            _ => f.write(self.description().as_bytes())
+        }
+    }
+}
+
+/// Why try_get_ref could not get a plugin.
+#[deriving(PartialEq, Show, Clone)]
+pub enum GetError {
+    /// No value was stored, and create failed.
+    CreateFailed,
+    /// A value of another type than the plugin's value type is stored under
+    /// its key, which means it was stored by something other than this crate.
+    TypeMismatch,
+    /// A plugin's create_mut panicked, so the stored plugins may be
+    /// inconsistent until clear_poison is called.
+    Poisoned
+}
+
+impl Error for GetError {
+    fn description(&self) -> &str {
+        match *self {
+            GetError::CreateFailed => "plugin creation failed",
+            GetError::TypeMismatch => "stored plugin value has another type",
+            GetError::Poisoned => "plugin storage poisoned by a panic"
+        }
+    }
+}
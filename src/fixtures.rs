@@ -0,0 +1,43 @@
+//! The extensible type and plugins shared by the tests of the modules.
+
+use anymap::AnyMap;
+use {Extensible, Key, PluginFor};
+use flush::FlushOnDrop;
+
+/// An extensible type with nothing but its extension storage, which flushes
+/// its plugins when dropped.
+pub struct Extended {
+    map: AnyMap
+}
+
+impl Extended {
+    pub fn new() -> Extended {
+        Extended { map: AnyMap::new() }
+    }
+}
+
+impl_extensible!(Extended, map)
+
+impl FlushOnDrop for Extended {}
+
+impl Drop for Extended {
+    fn drop(&mut self) { self.flush_plugins() }
+}
+
+/// A plugin which is always created, as 1.
+pub struct One;
+
+impl Key for One { type Value = uint; }
+
+impl PluginFor<Extended> for One {
+    fn create(_: &Extended) -> Option<uint> { Some(1) }
+}
+
+/// A plugin which is never created.
+pub struct Missing;
+
+impl Key for Missing { type Value = uint; }
+
+impl PluginFor<Extended> for Missing {
+    fn create(_: &Extended) -> Option<uint> { None }
+}
@@ -0,0 +1,130 @@
+//! Flushing of plugins in a fixed order before an extensible type is dropped.
+//!
+//! The values in an AnyMap are dropped in no particular order, so plugins
+//! which must be torn down in order are registered and flushed in the order
+//! of their registration instead.
+
+use std::intrinsics::TypeId;
+use anymap::AnyMap;
+use {Extensible, Key};
+use raw;
+
+/// A plugin whose value must be flushed before its extensible type is
+/// dropped, such as a buffer of pending writes.
+pub trait Flush: Key {
+    /// Flush a value of Self. The value is dropped as usual afterwards.
+    fn flush(value: &mut Self::Value);
+}
+
+/// An extensible type which flushes its registered plugins in order when
+/// flush_plugins is called.
+///
+/// Core traits can't require Drop, so implement this for the type and call
+/// flush_plugins from its Drop, leaving the values to be dropped with the
+/// type afterwards:
+///
+/// ```ignore
+/// impl FlushOnDrop for Request {}
+///
+/// impl Drop for Request {
+///     fn drop(&mut self) { self.flush_plugins() }
+/// }
+/// ```
+pub trait FlushOnDrop: Extensible {
+    /// Registers T to be flushed by flush_plugins, after every plugin
+    /// registered before it. Registering T again does not move it.
+    fn register_flush<T: Flush>(&mut self) {
+        let registered = raw::get_or_insert_with::<FlushesKey>(self.extensions_mut(), Vec::new);
+        let id = TypeId::of::<T>();
+        if !registered.iter().any(|flusher| flusher.id == id) {
+            registered.push(Flusher { id: id, flush: flush::<T> });
+        }
+    }
+
+    /// Flushes the stored value of every registered plugin in the order of
+    /// registration, skipping plugins which are not stored, then forgets the
+    /// registrations, so calling this again does nothing.
+    fn flush_plugins(&mut self) {
+        let map = self.extensions_mut();
+        for flusher in raw::remove::<FlushesKey>(map).unwrap_or(Vec::new()).iter() {
+            (flusher.flush)(map);
+        }
+    }
+}
+
+/// The key under which the plugins registered for flushing are stored.
+pub struct FlushesKey;
+
+impl Key for FlushesKey { type Value = Vec<Flusher>; }
+
+bookkeeping!(FlushesKey)
+
+/// A plugin registered for flushing.
+pub struct Flusher {
+    id: TypeId,
+    flush: fn(&mut AnyMap)
+}
+
+fn flush<K: Flush>(map: &mut AnyMap) {
+    match raw::find_mut::<K>(map) {
+        Some(value) => <K as Flush>::flush(value),
+        None => ()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use {GetCached, Key};
+    use fixtures::Extended;
+    use super::Flush;
+
+    struct Buffer(&'static str, Rc<RefCell<Vec<&'static str>>>);
+
+    struct First;
+
+    impl Key for First { type Value = Buffer; }
+
+    impl Flush for First {
+        fn flush(buffer: &mut Buffer) {
+            let Buffer(name, ref log) = *buffer;
+            log.borrow_mut().push(name);
+        }
+    }
+
+    struct Second;
+
+    impl Key for Second { type Value = Buffer; }
+
+    impl Flush for Second {
+        fn flush(buffer: &mut Buffer) {
+            let Buffer(name, ref log) = *buffer;
+            log.borrow_mut().push(name);
+        }
+    }
+
+    struct Unstored;
+
+    impl Key for Unstored { type Value = Buffer; }
+
+    impl Flush for Unstored {
+        fn flush(_: &mut Buffer) { panic!("Unstored is not stored") }
+    }
+
+    #[test] fn test_flush_on_drop() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        {
+            let mut logged = Extended::new();
+            logged.register_flush::<Second>();
+            logged.register_flush::<Unstored>();
+            logged.register_flush::<First>();
+            logged.register_flush::<Second>();
+            logged.clear();
+            logged.insert::<First>(Buffer("first", log.clone()));
+            logged.insert::<Second>(Buffer("second", log.clone()));
+            assert_eq!(logged.len(), 2)
+        }
+        assert_eq!(*log.borrow(), vec!["second", "first"])
+    }
+}
@@ -0,0 +1,299 @@
+//! Notification of plugin observers, and the crate's own bookkeeping entries.
+
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::intrinsics::TypeId;
+use std::rc::Rc;
+use anymap::AnyMap;
+use {Extensible, Key, PluginObserver};
+use {categories, clones, dynamic, flush, lru, names, raw, sizes, versioned};
+
+#[cfg(feature = "stats")]
+use stats::StatsKey;
+
+/// Whether get and get_result store the values they create, which the
+/// no-cache feature turns off.
+#[cfg(not(feature = "no-cache"))]
+pub const CACHING: bool = true;
+
+/// Whether get and get_result store the values they create, which the
+/// no-cache feature turns off.
+#[cfg(feature = "no-cache")]
+pub const CACHING: bool = false;
+
+/// The key under which the observer of an extensible type is stored.
+pub struct ObserverKey;
+
+impl Key for ObserverKey { type Value = Box<PluginObserver + 'static>; }
+
+/// Tells the observer of extensible, if any, that a value was created and
+/// stored for K.
+pub fn created<K: Key, E: Extensible>(extensible: &E) {
+    created_id(extensible, TypeId::of::<K>())
+}
+
+/// Tells the observer of extensible, if any, that a value was created and
+/// stored for the key with TypeId id.
+pub fn created_id<E: Extensible>(extensible: &E, id: TypeId) {
+    match raw::find::<ObserverKey>(extensible.extensions()) {
+        Some(observer) => observer.created(id),
+        None => ()
+    }
+}
+
+/// Tells the observer in map, if any, that the value stored for the key with
+/// TypeId id was removed.
+pub fn removed(map: &AnyMap, id: TypeId) {
+    match raw::find::<ObserverKey>(map) {
+        Some(observer) => observer.removed(id),
+        None => ()
+    }
+}
+
+/// The key under which the state checked on every access of an extensible
+/// type is stored, so that each access finds it in one lookup.
+struct StateKey;
+
+impl Key for StateKey { type Value = State; }
+
+/// Whether an extensible type is frozen and has a capacity, and its
+/// poisoned flag, shared with the guards of the creations running on it.
+struct State {
+    frozen: bool,
+    bounded: bool,
+    poisoned: Rc<Cell<bool>>
+}
+
+/// What an access must check before it returns or creates a value.
+#[deriving(Copy)]
+pub struct Flags {
+    /// The extensible type is frozen, so create must not be called.
+    pub frozen: bool,
+    /// A create_mut call panicked, so no value may be returned.
+    pub poisoned: bool,
+    /// A capacity is set, so accesses must be tracked by lru::touch.
+    pub bounded: bool
+}
+
+/// Returns the flags of the extensible type owning map.
+pub fn flags(map: &AnyMap) -> Flags {
+    match raw::find::<StateKey>(map) {
+        Some(state) => Flags {
+            frozen: state.frozen,
+            poisoned: state.poisoned.get(),
+            bounded: state.bounded
+        },
+        None => Flags { frozen: false, poisoned: false, bounded: false }
+    }
+}
+
+fn state_mut(map: &mut AnyMap) -> &mut State {
+    raw::get_or_insert_with::<StateKey>(map, unrestricted)
+}
+
+fn unrestricted() -> State {
+    State { frozen: false, bounded: false, poisoned: Rc::new(Cell::new(false)) }
+}
+
+/// Returns true if the extensible type owning map is frozen, so missing
+/// plugins must not be created.
+pub fn is_frozen(map: &AnyMap) -> bool {
+    flags(map).frozen
+}
+
+/// Freezes or unfreezes the extensible type owning map.
+pub fn set_frozen(map: &mut AnyMap, frozen: bool) {
+    state_mut(map).frozen = frozen;
+}
+
+/// Records that a capacity is set for the extensible type owning map.
+pub fn set_bounded(map: &mut AnyMap) {
+    state_mut(map).bounded = true;
+}
+
+/// Returns true if a create_mut call on the extensible type owning map
+/// panicked, and clear_poison has not been called since.
+pub fn is_poisoned(map: &AnyMap) -> bool {
+    flags(map).poisoned
+}
+
+/// Marks the extensible type owning map as no longer poisoned, forgetting
+/// the creations which the panic interrupted.
+pub fn clear_poison(map: &mut AnyMap) {
+    match raw::find::<StateKey>(map) {
+        Some(state) => state.poisoned.set(false),
+        None => return
+    }
+    raw::remove::<InProgressKey>(map);
+}
+
+/// Poisons an extensible type when dropped, unless it is disarmed first.
+///
+/// Hold one while create_mut runs, disarming it when create_mut returns, so
+/// that if create_mut panics, unwinding drops it armed. It holds the flag
+/// rather than the extensible type, which create_mut borrows.
+pub struct PoisonGuard {
+    poisoned: Option<Rc<Cell<bool>>>
+}
+
+/// Returns an armed guard for the extensible type owning map.
+pub fn poison_guard(map: &mut AnyMap) -> PoisonGuard {
+    PoisonGuard { poisoned: Some(state_mut(map).poisoned.clone()) }
+}
+
+impl PoisonGuard {
+    /// Drops the guard without poisoning anything.
+    pub fn disarm(mut self) {
+        self.poisoned = None;
+    }
+}
+
+impl Drop for PoisonGuard {
+    fn drop(&mut self) {
+        match self.poisoned {
+            Some(ref poisoned) => poisoned.set(true),
+            None => ()
+        }
+    }
+}
+
+/// The key under which the plugins currently being created through
+/// create_mut are stored.
+struct InProgressKey;
+
+impl Key for InProgressKey { type Value = HashSet<TypeId>; }
+
+/// Records that K is being created, returning false if it already was, in
+/// which case its creation depends on itself.
+pub fn begin_creation<K: Key, E: Extensible>(extensible: &mut E) -> bool {
+    raw::get_or_insert_with::<InProgressKey>(extensible.extensions_mut(), HashSet::new)
+        .insert(TypeId::of::<K>())
+}
+
+/// Records that K is no longer being created.
+pub fn end_creation<K: Key, E: Extensible>(extensible: &mut E) {
+    let map = extensible.extensions_mut();
+    let empty = match raw::find_mut::<InProgressKey>(map) {
+        Some(in_progress) => {
+            in_progress.remove(&TypeId::of::<K>());
+            in_progress.is_empty()
+        },
+        None => false
+    };
+    if empty {
+        raw::remove::<InProgressKey>(map);
+    }
+}
+
+/// Records that an access of K found its value stored.
+#[cfg(feature = "stats")]
+pub fn hit<K: Key, E: Extensible>(extensible: &mut E) {
+    stats_mut(extensible.extensions_mut()).record_hit::<K>();
+}
+
+/// Records that an access of K found its value stored.
+#[cfg(not(feature = "stats"))]
+#[inline(always)]
+pub fn hit<K: Key, E: Extensible>(_: &mut E) {}
+
+/// Records that an access of K called create.
+#[cfg(feature = "stats")]
+pub fn miss<K: Key, E: Extensible>(extensible: &mut E) {
+    stats_mut(extensible.extensions_mut()).record_miss::<K>();
+}
+
+/// Records that an access of K called create.
+#[cfg(not(feature = "stats"))]
+#[inline(always)]
+pub fn miss<K: Key, E: Extensible>(_: &mut E) {}
+
+/// The time at which a call of create started, when profiling.
+#[cfg(feature = "profiling")]
+pub type Start = u64;
+
+/// The time at which a call of create started, when profiling.
+#[cfg(not(feature = "profiling"))]
+pub type Start = ();
+
+/// Returns the time at which a call of create starts.
+#[cfg(feature = "profiling")]
+pub fn start() -> Start {
+    ::time::precise_time_ns()
+}
+
+/// Returns the time at which a call of create starts.
+#[cfg(not(feature = "profiling"))]
+#[inline(always)]
+pub fn start() -> Start {}
+
+/// Records that a call of create for K, which began at start, returned.
+#[cfg(feature = "profiling")]
+pub fn finish<K: Key, E: Extensible>(extensible: &mut E, start: Start) {
+    let ns = ::time::precise_time_ns() - start;
+    stats_mut(extensible.extensions_mut()).record_create::<K>(ns);
+}
+
+/// Records that a call of create for K, which began at start, returned.
+#[cfg(not(feature = "profiling"))]
+#[inline(always)]
+pub fn finish<K: Key, E: Extensible>(_: &mut E, _: Start) {}
+
+#[cfg(feature = "stats")]
+fn stats_mut(map: &mut AnyMap) -> &mut ::stats::PluginStats {
+    raw::get_or_insert_with::<StatsKey>(map, Default::default)
+}
+
+/// The keys of one module's bookkeeping entries, as declared by bookkeeping!.
+pub struct BookkeepingKeys {
+    /// Returns true if id is the TypeId of one of the keys.
+    pub owns: fn(&TypeId) -> bool,
+    /// Returns the number of the keys stored in map.
+    pub stored: fn(&AnyMap) -> uint
+}
+
+bookkeeping!(ObserverKey, InProgressKey, StateKey)
+
+/// Returns true if id is the TypeId of one of the crate's own bookkeeping
+/// entries, which are kept by clear and not counted as plugins.
+pub fn is_bookkeeping(id: &TypeId) -> bool {
+    modules().iter().any(|keys| match *keys {
+        Some(ref keys) => (keys.owns)(id),
+        None => false
+    })
+}
+
+/// Returns the number of bookkeeping entries in map.
+pub fn bookkeeping_len(map: &AnyMap) -> uint {
+    modules().iter().fold(0, |len, keys| match *keys {
+        Some(ref keys) => len + (keys.stored)(map),
+        None => len
+    })
+}
+
+// Every module which stores bookkeeping entries.
+fn modules() -> [Option<BookkeepingKeys>, ..13] {
+    [Some(bookkeeping_keys()), Some(::bookkeeping_keys()),
+     Some(categories::bookkeeping_keys()), Some(clones::bookkeeping_keys()),
+     Some(dynamic::bookkeeping_keys()), Some(flush::bookkeeping_keys()),
+     Some(lru::bookkeeping_keys()), Some(names::bookkeeping_keys()),
+     Some(sizes::bookkeeping_keys()), Some(versioned::bookkeeping_keys()),
+     stats_keys(), snapshot_keys(), order_keys()]
+}
+
+#[cfg(feature = "stats")]
+fn stats_keys() -> Option<BookkeepingKeys> { Some(::stats::bookkeeping_keys()) }
+
+#[cfg(not(feature = "stats"))]
+fn stats_keys() -> Option<BookkeepingKeys> { None }
+
+#[cfg(feature = "serde")]
+fn snapshot_keys() -> Option<BookkeepingKeys> { Some(::snapshot::bookkeeping_keys()) }
+
+#[cfg(not(feature = "serde"))]
+fn snapshot_keys() -> Option<BookkeepingKeys> { None }
+
+#[cfg(feature = "ordered")]
+fn order_keys() -> Option<BookkeepingKeys> { Some(::order::bookkeeping_keys()) }
+
+#[cfg(not(feature = "ordered"))]
+fn order_keys() -> Option<BookkeepingKeys> { None }
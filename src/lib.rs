@@ -2,12 +2,24 @@
 #![deny(missing_doc)]
 #![deny(warnings)]
 
-#![feature(macro_rules)]
+#![feature(macro_rules, associated_types, unboxed_closures, unsafe_destructor)]
 
 //! Lazily-Evaluated, Order-Independent Plugins for Extensible Types.
 
 extern crate anymap;
+#[cfg(feature = "profiling")] extern crate time;
+#[cfg(test)] extern crate test;
+
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::{Occupied, Vacant};
+use std::error::FromError;
+use std::hash::Hash;
+use std::intrinsics::TypeId;
+use std::mem;
+use std::rc::{Rc, Weak};
 use anymap::AnyMap;
+use access::Lookup;
 
 macro_rules! try_option (
     ($e:expr) => {
@@ -18,136 +30,3202 @@ macro_rules! try_option (
     }
 )
 
+// Declares the keys of a module's bookkeeping entries, which clear keeps and
+// len, cached_type_ids and retain skip. Each module declares its own keys
+// beside them, and is listed in hooks::modules.
+macro_rules! bookkeeping (
+    ($($key:ty),+) => {
+        /// Returns the keys of this module's bookkeeping entries.
+        #[doc(hidden)]
+        pub fn bookkeeping_keys() -> ::hooks::BookkeepingKeys {
+            fn owns(id: &::std::intrinsics::TypeId) -> bool {
+                $(*id == ::std::intrinsics::TypeId::of::<$key>())||+
+            }
+
+            fn stored(map: &::anymap::AnyMap) -> uint {
+                let raw = map.as_raw();
+                0 $(+ raw.contains_key(&::std::intrinsics::TypeId::of::<$key>()) as uint)+
+            }
+
+            ::hooks::BookkeepingKeys { owns: owns, stored: stored }
+        }
+    }
+)
+
+/// Implements Extensible for a type, using the named field, which must be an
+/// AnyMap, as its extension storage.
+///
+/// Extensible and AnyMap must be in scope where this is used.
+///
+/// ```ignore
+/// struct Request {
+///     url: String,
+///     map: AnyMap
+/// }
+///
+/// impl_extensible!(Request, map)
+/// ```
+#[macro_export]
+macro_rules! impl_extensible (
+    ($t:ty, $field:ident) => {
+        impl Extensible for $t {
+            fn extensions(&self) -> &AnyMap { &self.$field }
+            fn extensions_mut(&mut self) -> &mut AnyMap { &mut self.$field }
+        }
+    }
+)
+
+/// Declares a struct with an extra field named extensions, an AnyMap, and
+/// implements Extensible for it using that field, so plugins of the struct
+/// are cached through GetCached. The struct and each of its fields may be
+/// declared pub; the extensions field is always private.
+///
+/// Extensible and AnyMap must be in scope where this is used, and the
+/// extensions field must be set when the struct is built, so a pub struct
+/// needs a constructor in the module declaring it.
+///
+/// ```ignore
+/// plugin_cache!(pub struct Point { pub x: int, y: int })
+///
+/// let point = Point { x: 1, y: 2, extensions: AnyMap::new() };
+/// ```
+#[macro_export]
+macro_rules! plugin_cache (
+    ($(#[$attr:meta])* pub struct $name:ident { $($fields:tt)* }) => {
+        plugin_cache_fields!($name ($(#[$attr])* pub) () $($fields)*)
+    };
+    ($(#[$attr:meta])* struct $name:ident { $($fields:tt)* }) => {
+        plugin_cache_fields!($name ($(#[$attr])*) () $($fields)*)
+    }
+)
+
+// Collects the fields of a plugin_cache! struct one at a time, as each may
+// be pub, then declares the struct. Must be in scope with plugin_cache!.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! plugin_cache_fields (
+    ($name:ident ($($head:tt)*) ($($done:tt)*)) => {
+        $($head)* struct $name {
+            $($done)*
+            extensions: AnyMap
+        }
+
+        impl_extensible!($name, extensions)
+    };
+    ($name:ident $head:tt ($($done:tt)*) pub $field:ident: $t:ty, $($rest:tt)*) => {
+        plugin_cache_fields!($name $head ($($done)* pub $field: $t,) $($rest)*)
+    };
+    ($name:ident $head:tt ($($done:tt)*) pub $field:ident: $t:ty) => {
+        plugin_cache_fields!($name $head ($($done)* pub $field: $t,))
+    };
+    ($name:ident $head:tt ($($done:tt)*) $field:ident: $t:ty, $($rest:tt)*) => {
+        plugin_cache_fields!($name $head ($($done)* $field: $t,) $($rest)*)
+    };
+    ($name:ident $head:tt ($($done:tt)*) $field:ident: $t:ty) => {
+        plugin_cache_fields!($name $head ($($done)* $field: $t,))
+    }
+)
+
+/// Implements PluginFor a source type for each of several plugins whose value
+/// is its default, ignoring the source.
+///
+/// PluginFor and each plugin's Key implementation must be in scope where
+/// this is used, and each value type must implement Default.
+///
+/// ```ignore
+/// default_plugin!(Request, Limits, Settings)
+/// ```
+#[macro_export]
+macro_rules! default_plugin (
+    ($source:ty, $($plugin:ty),+) => {
+        $(
+            impl PluginFor<$source> for $plugin {
+                fn create(_: &$source) -> Option<<$plugin as Key>::Value> {
+                    Some(::std::default::Default::default())
+                }
+            }
+        )+
+    }
+)
+
+/// Gets several plugins from an extensible value at once, as get does,
+/// returning a tuple of their values, each of which is an Option.
+///
+/// Every plugin is stored as a side effect. GetCached must be in scope where
+/// this is used, and tuples of more than 12 values do not implement the
+/// usual traits.
+///
+/// ```ignore
+/// let (url, headers) = get_all!(request, Url, Headers);
+/// ```
+#[macro_export]
+macro_rules! get_all (
+    ($e:expr, $($t:ty),+) => ({
+        let extensible = &mut $e;
+        ($(extensible.get::<$t>(),)+)
+    })
+)
+
+/// Computes several plugins from a value at once, as compute does, returning
+/// a tuple of their values, each of which is an Option.
+///
+/// Nothing is stored. Get must be in scope where this is used, and tuples of
+/// more than 12 values do not implement the usual traits.
+///
+/// ```ignore
+/// let (width, height) = compute_all!(image, Width, Height);
+/// ```
+#[macro_export]
+macro_rules! compute_all (
+    ($e:expr, $($t:ty),+) => ({
+        let source = &$e;
+        ($(source.compute::<$t>(),)+)
+    })
+)
+
+/// Precomputes several plugins of an extensible value at once, as precompute
+/// does, returning true if every one of them is stored afterwards.
+///
+/// Every plugin is precomputed even if an earlier one fails. GetCached must
+/// be in scope where this is used.
+///
+/// ```ignore
+/// if !ensure_all!(request, Url, Headers, Cookies) {
+///     return Err(NotReady);
+/// }
+/// ```
+#[macro_export]
+macro_rules! ensure_all (
+    ($e:expr, $($t:ty),+) => ({
+        let extensible = &mut $e;
+        let mut all = true;
+        $(all = extensible.precompute::<$t>() && all;)+
+        all
+    })
+)
+
+pub use boxed::BoxedPlugin;
+pub use context::PluginContext;
+pub use cow::PluginCow;
+pub use debug::PluginDebug;
+pub use entry::PluginEntry;
+pub use error::{GetError, PluginError};
+pub use flush::{Flush, FlushOnDrop};
+pub use map::{ExtensibleBuilder, ExtensibleMap};
+pub use mapped::{MapPlugin, Mapper};
+pub use raw::TypeIds;
+pub use scope::PluginScope;
+pub use sizes::SizeHint;
+pub use storage::{GetCachedMap, MapExtensible, Storage};
+pub use thread_cache::clear as clear_thread_cache;
+pub use wrapper::Extended;
+
+pub mod boxed;
+pub mod cell;
+pub mod context;
+pub mod cow;
+pub mod debug;
+pub mod entry;
+pub mod error;
+pub mod map;
+pub mod mapped;
+pub mod storage;
+pub mod sync;
+pub mod wrapper;
+#[cfg(feature = "stats")]
+pub mod stats;
+#[cfg(feature = "serde")]
+pub mod snapshot;
+#[cfg(feature = "test-util")]
+pub mod testing;
+#[cfg(feature = "ordered")]
+mod order;
+#[cfg(test)]
+mod fixtures;
+mod access;
+mod categories;
+mod clones;
+mod dynamic;
+mod flush;
+mod hooks;
+mod lru;
+mod names;
+mod raw;
+mod scope;
+mod sizes;
+mod thread_cache;
+mod tuples;
+mod versioned;
+
 /// Defines an interface that extensible types must implement.
 ///
-/// Extensible types must contain a AnyMap.
+/// Extensible types must contain a AnyMap. Types storing plugins in another
+/// Storage implement MapExtensible instead.
 pub trait Extensible {
     /// Get a reference to the type's extension storage.
     fn extensions(&self) -> &AnyMap;
 
     /// Get a mutable reference to the type's extension storage.
     fn extensions_mut(&mut self) -> &mut AnyMap;
+
+    /// Get the type's current generation. Plugins stored at an earlier
+    /// generation are created again on their next access.
+    ///
+    /// Defaults to always 0, so plugins are never invalidated.
+    fn generation(&self) -> u64 { 0 }
+
+    /// Get a hash of the content plugins are created from, which must change
+    /// whenever that content does. Plugins stored at another hash are created
+    /// again on their next access, as for an earlier generation.
+    ///
+    /// This is an alternative to advancing the generation by hand, for types
+    /// which can hash their content cheaply, as it is called on every access.
+    /// Defaults to None, so content is never hashed.
+    fn content_hash(&self) -> Option<u64> { None }
+}
+
+/// An extensible type which can be turned into its extension storage.
+///
+/// This can't be implemented generically, as it moves the storage out of the
+/// type.
+pub trait IntoExtensible: Extensible {
+    /// Consume self, returning its extension storage with every stored
+    /// plugin, including the crate's bookkeeping entries.
+    fn into_extensions(self) -> AnyMap;
 }
 
 /// Expose an interface for cacheing plugins.
+///
+/// Besides the values of plugins, the extension storage holds the crate's
+/// bookkeeping entries: the registrations made by the register methods here
+/// and by FlushOnDrop::register_flush, the observer, the frozen and poisoned
+/// state, and the like. clear keeps them, and len, cached_type_ids and retain
+/// skip them, so they are never counted as plugins.
 pub trait GetCached: Extensible {
-    /// Creates, stores and returns reference of T if construction of T
-    /// through T's implementation of create succeeds, otherwise None.
-    fn get_ref<T: PluginFor<Self> + 'static>(&mut self) -> Option<&T> {
-        let found = self.extensions().contains::<T>();
-        if found {
-            return self.extensions().find();
+    /// Creates, stores and returns reference of T's value if construction
+    /// through T's implementation of create succeeds, otherwise None. While
+    /// self is frozen, only a stored value is returned.
+    ///
+    /// This calls create at most once and never calls itself, so it adds one
+    /// stack frame whatever create does. create only gets a shared ref, so
+    /// it can't get T again through this method; see get_ref_dependent for
+    /// plugins which can.
+    fn get_ref<T: PluginFor<Self>>(&mut self) -> Option<&T::Value> {
+        let stored = access::create_and_store::<T, T, Self, _>(self, |: e: &mut Self| {
+            <T as PluginFor<Self>>::create(&*e)
+        });
+        if stored { raw::find::<T>(self.extensions()) } else { None }
+    }
+
+    /// Calls prep with self, then creates, stores and returns reference of
+    /// T's value as get_ref does. prep can store the plugins which T's create
+    /// relies on, through get_ref or insert.
+    ///
+    /// prep is called on every call, even if T's value is already stored, so
+    /// it should return early itself if its work can be skipped.
+    fn get_ref_with_deps<T: PluginFor<Self>, F: FnOnce(&mut Self)>(&mut self, prep: F)
+            -> Option<&T::Value> {
+        prep(self);
+        self.get_ref::<T>()
+    }
+
+    /// Creates, stores and returns reference of T's value as get_ref does,
+    /// along with true if create was called to do so, or false if the value
+    /// was already stored.
+    fn get_ref_status<T: PluginFor<Self>>(&mut self) -> (Option<&T::Value>, bool) {
+        let found = versioned::is_fresh::<T, Self>(self);
+        let flags = hooks::flags(self.extensions());
+        let created = !found && !flags.frozen && !flags.poisoned;
+        (self.get_ref::<T>(), created)
+    }
+
+    /// Creates, stores and returns reference of T's value as get_ref does,
+    /// but only calls create if pred returns true for self. If it returns
+    /// false, nothing is stored and None is returned. A stored value is
+    /// returned without calling pred.
+    fn get_ref_if<T: PluginFor<Self>, P: FnOnce(&Self) -> bool>(&mut self, pred: P)
+            -> Option<&T::Value> {
+        let found = versioned::is_fresh::<T, Self>(self);
+        if !found && !pred(&*self) {
+            return None;
         }
-        let t = try_option!(PluginFor::create(self));
-        self.extensions_mut().insert::<T>(t);
-        self.get_ref()
+        self.get_ref::<T>()
     }
 
-    /// Creates, stores and returns a mutable ref of T if construction of T
-    /// through T's implementation of create succeeds, otherwise None.
-    fn get_mut<T: PluginFor<Self> + 'static>(&mut self) -> Option<&mut T> {
-        let found = self.extensions().contains::<T>();
-        if found {
-            return self.extensions_mut().find_mut();
+    /// Creates, stores and returns reference of T's value as get_ref does,
+    /// but calls create up to attempts times until it succeeds. If every
+    /// attempt fails, nothing is stored and None is returned. Any delay
+    /// between attempts is up to create.
+    ///
+    /// Each attempt is counted and timed as one call of create.
+    fn get_ref_retry<T: PluginFor<Self>>(&mut self, attempts: uint) -> Option<&T::Value> {
+        match access::lookup::<T, T, Self>(self) {
+            Lookup::Fresh => return raw::find::<T>(self.extensions()),
+            Lookup::Unavailable => return None,
+            Lookup::Missing => ()
         }
-        let t = try_option!(PluginFor::create(self));
-        self.extensions_mut().insert::<T>(t);
-        self.get_mut()
+        for _ in range(0, attempts) {
+            let created = access::call::<T, Self, _, _>(self, |: e: &mut Self| {
+                <T as PluginFor<Self>>::create(&*e)
+            });
+            match created {
+                Some(t) => {
+                    access::store::<T, T, Self>(self, t);
+                    return raw::find::<T>(self.extensions());
+                },
+                None => ()
+            }
+        }
+        None
+    }
+
+    /// Creates, stores and returns reference of the boxed value of
+    /// BoxedPlugin<U> as get_ref does, as a reference to U itself.
+    fn get_ref_boxed<Sized? U: 'static>(&mut self) -> Option<&U>
+    where BoxedPlugin<U>: PluginFor<Self> {
+        self.get_ref::<BoxedPlugin<U>>().map(|boxed| &**boxed)
+    }
+
+    /// Creates, stores and returns reference of the value of T for key if
+    /// construction through create succeeds, otherwise None. create is called
+    /// with key only when no value is stored for it, so each distinct key is
+    /// created once, as when compiling one pattern per pattern string.
+    ///
+    /// The values of all keys are kept in one HashMap<K, T> stored for the
+    /// pair of K and T, which grows by one entry per distinct key and is
+    /// never pruned; remove the pair's map by clearing self. The map counts
+    /// as one plugin, for stats and for the capacity.
+    fn get_ref_by_key<K, T, F>(&mut self, key: K, create: F) -> Option<&T>
+    where K: Hash + Eq + 'static, T: 'static, F: FnOnce(&K) -> Option<T> {
+        let flags = hooks::flags(self.extensions());
+        if flags.poisoned {
+            return None;
+        }
+        let found = raw::find::<ByKey<K, T>>(self.extensions())
+            .map_or(false, |values| values.contains_key(&key));
+        let created = if found {
+            hooks::hit::<ByKey<K, T>, Self>(self);
+            None
+        } else if flags.frozen {
+            return None;
+        } else {
+            let key = &key;
+            Some(try_option!(access::call::<ByKey<K, T>, Self, _, _>(self, move |: _: &mut Self| {
+                create(key)
+            })))
+        };
+        // The pair's map is only stored once a value is, so a failed create
+        // leaves nothing behind.
+        match self.extensions_mut().as_raw_mut().entry(TypeId::of::<ByKey<K, T>>()) {
+            Occupied(_) => (),
+            Vacant(entry) => { entry.set(box HashMap::<K, T>::new() as Box<Any + 'static>); }
+        }
+        if flags.bounded {
+            lru::touch::<ByKey<K, T>, Self>(self);
+        }
+        match raw::find_mut::<ByKey<K, T>>(self.extensions_mut()).unwrap().entry(key) {
+            Occupied(entry) => Some(&*entry.into_mut()),
+            Vacant(entry) => Some(&*entry.set(created.unwrap()))
+        }
+    }
+
+    /// Creates, stores and returns reference of T's value as get_ref does,
+    /// then returns the part of it which f projects, such as one of its
+    /// fields.
+    fn map_ref<T, Sized? U, F>(&mut self, f: F) -> Option<&U>
+    where T: PluginFor<Self>, F: FnOnce(&T::Value) -> &U {
+        self.get_ref::<T>().map(f)
+    }
+
+    /// Creates, stores and returns reference of T's value as get_ref does,
+    /// falling back to creating U's value and converting it to T's if
+    /// construction of T fails. The converted value is stored under T, so
+    /// neither create is called again while it is stored; U's value is not
+    /// stored separately.
+    fn get_ref_or_alt<T, U>(&mut self) -> Option<&T::Value>
+    where T: PluginFor<Self> + FallbackFrom<U>, U: PluginFor<Self> {
+        let stored = access::create_and_store::<T, T, Self, _>(self, |: e: &mut Self| {
+            match <T as PluginFor<Self>>::create(&*e) {
+                Some(t) => Some(t),
+                None => <U as PluginFor<Self>>::create(&*e)
+                    .map(|u| <T as FallbackFrom<U>>::convert(u))
+            }
+        });
+        if stored { raw::find::<T>(self.extensions()) } else { None }
+    }
+
+    /// Creates, stores and returns a mutable ref of T's value if construction
+    /// through T's implementation of create succeeds, otherwise None. While
+    /// self is frozen, only a stored value is returned.
+    fn get_mut<T: PluginFor<Self>>(&mut self) -> Option<&mut T::Value> {
+        let stored = access::create_and_store::<T, T, Self, _>(self, |: e: &mut Self| {
+            <T as PluginFor<Self>>::create(&*e)
+        });
+        if stored { raw::find_mut::<T>(self.extensions_mut()) } else { None }
     }
 
-    /// Creates, stores and returns an instance of T if construction of T
+    /// Creates, stores and returns an instance of T's value if construction
     /// through T's implementation of create succeeds, otherwise None.
-    fn get<T: PluginFor<Self> + 'static + Clone>(&mut self) -> Option<T> {
-        let found = self.extensions().contains::<T>();
+    ///
+    /// While self is frozen, only a stored value is returned. With the
+    /// no-cache feature, this always calls create unless self is frozen, and
+    /// stores nothing. Methods returning references still store values.
+    ///
+    /// T's value must be Clone, so T implements CloneablePlugin; use get_ref
+    /// to borrow values which are not.
+    fn get<T: CloneablePlugin<Self>>(&mut self) -> Option<T::Value> {
+        if !hooks::CACHING {
+            let flags = hooks::flags(self.extensions());
+            if flags.frozen || flags.poisoned {
+                return None;
+            }
+            return <T as PluginFor<Self>>::create(self);
+        }
+        match access::lookup::<T, T, Self>(self) {
+            Lookup::Fresh => return raw::find::<T>(self.extensions())
+                .map(|c| <T as CloneablePlugin<Self>>::clone_value(c)),
+            Lookup::Unavailable => return None,
+            Lookup::Missing => ()
+        }
+        let t = try_option!(access::call::<T, Self, _, _>(self, |: e: &mut Self| {
+            <T as PluginFor<Self>>::create(&*e)
+        }));
+        access::store::<T, T, Self>(self, <T as CloneablePlugin<Self>>::clone_value(&t));
+        Some(t)
+    }
+
+    /// Creates, stores and returns T's value as get_ref does, borrowed from
+    /// the stored plugins, for callers which need ownership only sometimes
+    /// and can call into_owned then. With the no-cache feature, the value is
+    /// created as get creates it and returned owned.
+    fn get_cow<T: PluginFor<Self>>(&mut self) -> Option<PluginCow<T::Value>>
+    where T::Value: Clone {
+        if !hooks::CACHING {
+            let flags = hooks::flags(self.extensions());
+            if flags.frozen || flags.poisoned {
+                return None;
+            }
+            return <T as PluginFor<Self>>::create(self).map(|t| PluginCow::Owned(t));
+        }
+        self.get_ref::<T>().map(|t| PluginCow::Borrowed(t))
+    }
+
+    /// Creates, stores and returns a copy of T's value as get does, for
+    /// values which are Copy, such as counters and flags.
+    fn get_copy<T: PluginFor<Self>>(&mut self) -> Option<T::Value> where T::Value: Copy {
+        if !hooks::CACHING {
+            let flags = hooks::flags(self.extensions());
+            if flags.frozen || flags.poisoned {
+                return None;
+            }
+            return <T as PluginFor<Self>>::create(self);
+        }
+        self.get_ref::<T>().map(|value| *value)
+    }
+
+    /// Creates, stores and returns a reference of T's value if construction
+    /// through T's implementation of create succeeds, otherwise the error
+    /// produced by create. Nothing is stored on error.
+    ///
+    /// The only errors are those of create, so this calls create even while
+    /// self is frozen or poisoned, and returns stored values while poisoned.
+    fn get_ref_result<T: PluginForResult<Self>>(&mut self)
+            -> Result<&T::Value, <T as PluginForResult<Self>>::Error> {
+        if !access::found::<T, T, Self>(self) {
+            let t = try!(access::call::<T, Self, _, _>(self, |: e: &mut Self| {
+                <T as PluginForResult<Self>>::create(&*e)
+            }));
+            access::store::<T, T, Self>(self, t);
+        }
+        Ok(raw::find::<T>(self.extensions()).unwrap())
+    }
+
+    /// Creates, stores and returns a mutable ref of T's value if construction
+    /// through T's implementation of create succeeds, otherwise the error
+    /// produced by create. Nothing is stored on error.
+    fn get_mut_result<T: PluginForResult<Self>>(&mut self)
+            -> Result<&mut T::Value, <T as PluginForResult<Self>>::Error> {
+        if !access::found::<T, T, Self>(self) {
+            let t = try!(access::call::<T, Self, _, _>(self, |: e: &mut Self| {
+                <T as PluginForResult<Self>>::create(&*e)
+            }));
+            access::store::<T, T, Self>(self, t);
+        }
+        Ok(raw::find_mut::<T>(self.extensions_mut()).unwrap())
+    }
+
+    /// Creates, stores and returns an instance of T's value if construction
+    /// through T's implementation of create succeeds, otherwise the error
+    /// produced by create. Nothing is stored on error.
+    ///
+    /// With the no-cache feature, this always calls create and stores
+    /// nothing.
+    fn get_result<T: PluginForResult<Self>>(&mut self)
+            -> Result<T::Value, <T as PluginForResult<Self>>::Error> where T::Value: Clone {
+        if !hooks::CACHING {
+            return <T as PluginForResult<Self>>::create(self);
+        }
+        if access::found::<T, T, Self>(self) {
+            return Ok(raw::find::<T>(self.extensions()).unwrap().clone());
+        }
+        let t = try!(access::call::<T, Self, _, _>(self, |: e: &mut Self| {
+            <T as PluginForResult<Self>>::create(&*e)
+        }));
+        access::store::<T, T, Self>(self, t.clone());
+        Ok(t)
+    }
+
+    /// Creates and returns an instance of T's value through T's
+    /// implementation of create, without looking at or storing anything.
+    ///
+    /// Unlike get, this calls create even if a value is stored under T, and
+    /// leaves that value as it is. Use it for large values read only once.
+    fn compute_uncached<T: PluginFor<Self>>(&self) -> Option<T::Value> {
+        <T as PluginFor<Self>>::create(self)
+    }
+
+    /// Creates, stores and returns reference of T's value as get_ref does,
+    /// returning PluginError::NotAvailable if construction fails.
+    fn get_ref_err<T: PluginFor<Self>>(&mut self) -> Result<&T::Value, PluginError> {
+        self.get_ref::<T>().ok_or(PluginError::NotAvailable)
+    }
+
+    /// Creates, stores and returns reference of T's value if construction
+    /// through T's implementation of create with args succeeds, otherwise
+    /// None.
+    ///
+    /// The value is stored under T alone, so once one is stored, later calls
+    /// return it whatever their args: the first successful call's args win.
+    /// Use compute_with to create a value for each set of args.
+    fn get_with<T: PluginForArgs<Self, A>, A>(&mut self, args: A) -> Option<&T::Value> {
+        let stored = access::create_and_store::<T, T, Self, _>(self, move |: e: &mut Self| {
+            <T as PluginForArgs<Self, A>>::create(&*e, args)
+        });
+        if stored { raw::find::<T>(self.extensions()) } else { None }
+    }
+
+    /// Creates, stores and returns reference of T's value under name if
+    /// construction through T's implementation of create succeeds, otherwise
+    /// None. Each name has its own value, created separately.
+    ///
+    /// The values of all names are kept in one HashMap stored for T, so each
+    /// access hashes name on top of the usual lookup. Nothing is stored under
+    /// T itself, and the names of T count as one plugin.
+    fn get_ref_named<T: PluginFor<Self>>(&mut self, name: &'static str) -> Option<&T::Value> {
+        let flags = hooks::flags(self.extensions());
+        if flags.poisoned {
+            return None;
+        }
+        let found = match raw::find::<Named<T>>(self.extensions()) {
+            Some(named) => named.contains_key(&name),
+            None => false
+        };
         if found {
-            return self.extensions().find::<T>().map(|c| c.clone());
+            hooks::hit::<T, Self>(self);
+        } else {
+            if flags.frozen {
+                return None;
+            }
+            let t = try_option!(access::call::<T, Self, _, _>(self, |: e: &mut Self| {
+                <T as PluginFor<Self>>::create(&*e)
+            }));
+            self.insert_named::<T>(name, t);
         }
-        let t = try_option!(PluginFor::create(self));
-        self.extensions_mut().insert::<T>(t);
-        self.get()
+        if flags.bounded {
+            lru::touch::<Named<T>, Self>(self);
+        }
+        raw::find::<Named<T>>(self.extensions()).and_then(|named| named.get(&name))
     }
-}
 
-/// An interface for getting plugins on non-extensible types.
-pub trait Get {
-    /// Call the appropriate PluginFor implementation to create an instance
-    /// of T.
-    fn compute<T: PluginFor<Self>>(&self) -> Option<T> {
-        PluginFor::create(self)
+    /// Stores value under name for T without calling create, returning the
+    /// value previously stored under that name, if any.
+    fn insert_named<T: Key>(&mut self, name: &'static str, value: T::Value) -> Option<T::Value> {
+        raw::get_or_insert_with::<Named<T>>(self.extensions_mut(), HashMap::new).insert(name, value)
     }
-}
 
-impl<T> Get for T {}
-impl<T: Extensible> GetCached for T {}
+    /// Creates, stores and returns reference of T's value as get_ref does,
+    /// returning GetError::CreateFailed if construction fails,
+    /// GetError::TypeMismatch if a value of another type is stored under T,
+    /// or GetError::Poisoned if self is poisoned. Unlike get_ref, this never
+    /// panics in debug builds on a mismatch.
+    fn try_get_ref<T: PluginFor<Self>>(&mut self) -> Result<&T::Value, GetError> {
+        if hooks::is_poisoned(self.extensions()) {
+            return Err(GetError::Poisoned);
+        }
+        if !raw::matches::<T>(self.extensions()) {
+            return Err(GetError::TypeMismatch);
+        }
+        self.get_ref::<T>().ok_or(GetError::CreateFailed)
+    }
 
-/// Implementations of this trait can act as plugins for `T`, via `T::get<P>()`
-pub trait PluginFor<T> {
-    /// Create Self from an instance of T. This will be called only once.
-    fn create(&T) -> Option<Self>;
-}
+    /// Creates and stores T's value as get_mut_result does, then calls f
+    /// with a mutable ref of it. If f fails, the value is removed, so that
+    /// its changes are not kept, and it is created again on its next access.
+    ///
+    /// Errors from construction are converted to the error type of f. Like
+    /// get_mut_result, this ignores freeze and poisoning.
+    fn try_with<T, E, F>(&mut self, f: F) -> Result<(), E>
+    where T: PluginForResult<Self>,
+          E: FromError<<T as PluginForResult<Self>>::Error>,
+          F: FnOnce(&mut T::Value) -> Result<(), E> {
+        let result = f(try!(self.get_mut_result::<T>()));
+        if result.is_err() {
+            self.remove::<T>();
+        }
+        result
+    }
 
-#[cfg(test)]
-mod test {
-    use anymap::AnyMap;
-    use super::{Extensible, PluginFor, GetCached};
+    /// Creates and stores T's value as get_mut does, then calls f with a
+    /// mutable ref of it, returning true if construction succeeded. If it
+    /// failed, f is not called.
+    ///
+    /// The generation is defined by the extensible type, so this doesn't
+    /// change it; plugins created from T's value are not invalidated.
+    fn modify<T: PluginFor<Self>, F: FnOnce(&mut T::Value)>(&mut self, f: F) -> bool {
+        match self.get_mut::<T>() {
+            Some(t) => { f(t); true },
+            None => false
+        }
+    }
 
-    struct Extended {
-        map: AnyMap
+    /// Removes and returns the value stored under T, if any. The next access
+    /// of T will call T's implementation of create again.
+    fn remove<T: Key>(&mut self) -> Option<T::Value> {
+        let removed = raw::remove::<T>(self.extensions_mut());
+        if removed.is_some() {
+            hooks::removed(self.extensions(), TypeId::of::<T>());
+        }
+        removed
     }
 
-    impl Extended {
-        fn new() -> Extended {
-            Extended { map: AnyMap::new() }
+    /// Stores value under T without calling create, returning the value
+    /// previously stored under T, if any.
+    ///
+    /// T need not be a plugin, so this can also be used to store arbitrary
+    /// extension data.
+    fn insert<T: Key>(&mut self, value: T::Value) -> Option<T::Value> {
+        versioned::insert::<T, Self>(self, value)
+    }
+
+    /// Returns true if a value is currently stored under T. This never calls
+    /// create.
+    fn contains<T: Key>(&self) -> bool {
+        raw::contains::<T>(self.extensions())
+    }
+
+    /// Returns a reference of the value stored under T, if one is stored and
+    /// is fresh, otherwise None. This never calls create, so it is safe to
+    /// use where creating T would be expensive or have side effects.
+    fn peek<T: Key>(&self) -> Option<&T::Value> {
+        if versioned::is_fresh::<T, Self>(self) {
+            raw::find::<T>(self.extensions())
+        } else {
+            None
         }
     }
 
-    impl Extensible for Extended {
-        fn extensions(&self) -> &AnyMap { &self.map }
-        fn extensions_mut(&mut self) -> &mut AnyMap { &mut self.map }
+    /// Returns a mutable ref of the value stored under T as peek does. This
+    /// never calls create.
+    fn peek_mut<T: Key>(&mut self) -> Option<&mut T::Value> {
+        if versioned::is_fresh::<T, Self>(self) {
+            raw::find_mut::<T>(self.extensions_mut())
+        } else {
+            None
+        }
     }
 
-    macro_rules! generate_plugin (
-        ($t:ty, $v:ident, $v2:expr) => {
-            #[deriving(PartialEq, Show, Clone)]
-            struct $v(uint);
+    /// Returns an instance of T's value as get does, falling back to default
+    /// if construction fails. The default is stored under T, so later
+    /// accesses of T return it without calling create again.
+    ///
+    /// While self is poisoned, get returns None even if a value is stored, so
+    /// the default is returned but not stored, leaving that value in place.
+    fn get_or<T: CloneablePlugin<Self>>(&mut self, default: T::Value) -> T::Value {
+        match self.get::<T>() {
+            Some(t) => t,
+            None => {
+                if !hooks::is_poisoned(self.extensions()) && !versioned::is_fresh::<T, Self>(self) {
+                    self.insert::<T>(<T as CloneablePlugin<Self>>::clone_value(&default));
+                }
+                default
+            }
+        }
+    }
 
-            impl PluginFor<Extended> for $t {
-                fn create(_: &Extended) -> Option<$t> { Some($v($v2)) }
+    /// Returns an instance of T's value as get does, falling back to the
+    /// result of f if construction fails. As with get_or, the fallback is
+    /// stored under T unless self is poisoned.
+    fn get_or_else<T: CloneablePlugin<Self>, F: FnOnce() -> T::Value>(&mut self, f: F)
+            -> T::Value {
+        match self.get::<T>() {
+            Some(t) => t,
+            None => {
+                let t = f();
+                if !hooks::is_poisoned(self.extensions()) && !versioned::is_fresh::<T, Self>(self) {
+                    self.insert::<T>(<T as CloneablePlugin<Self>>::clone_value(&t));
+                }
+                t
             }
         }
-    )
+    }
 
-    generate_plugin!(One, One, 1)
-    generate_plugin!(Two, Two, 2)
-    generate_plugin!(Three, Three, 3)
-    generate_plugin!(Four, Four, 4)
-    generate_plugin!(Five, Five, 5)
-    generate_plugin!(Six, Six, 6)
-    generate_plugin!(Seven, Seven, 7)
-    generate_plugin!(Eight, Eight, 8)
-    generate_plugin!(Nine, Nine, 9)
-    generate_plugin!(Ten, Ten, 10)
+    /// Eagerly creates and stores T's value, returning true if a value is
+    /// stored under T afterwards. Does not call create if T is already stored.
+    fn precompute<T: PluginFor<Self>>(&mut self) -> bool {
+        self.get_ref::<T>().is_some()
+    }
 
-    #[test] fn test_simple() {
-        let mut extended = Extended::new();
-        assert_eq!(extended.get_ref::<One>(),   Some(&One(1)))
-        assert_eq!(extended.get_ref::<Two>(),   Some(&Two(2)))
-        assert_eq!(extended.get_ref::<Three>(), Some(&Three(3)))
+    /// Removes every stored plugin, so that each will be created again on
+    /// its next access. The observer and stats are kept.
+    fn clear(&mut self) {
+        raw::retain(self.extensions_mut(), |&mut: id: &TypeId| hooks::is_bookkeeping(id))
     }
 
-    #[test] fn test_resize() {
-        let mut extended = Extended::new();
-        extended.get::<One>();
-        extended.get::<Two>();
-        extended.get::<Three>();
-        extended.get::<Four>();
-        extended.get::<Five>();
-        extended.get::<Six>();
-        extended.get::<Seven>();
-        extended.get::<Eight>();
-        extended.get::<Nine>();
-        extended.get::<Ten>();
-        assert_eq!(extended.get_ref::<One>(), Some(&One(1)))
+    /// Removes every stored plugin for which keep returns false, given the
+    /// TypeId of its key. keep is called once for each stored plugin, as in
+    /// cached_type_ids, in no particular order. The observer and stats are
+    /// kept.
+    fn retain<F: FnMut(TypeId) -> bool>(&mut self, mut keep: F) {
+        raw::retain(self.extensions_mut(), |&mut: id: &TypeId| {
+            hooks::is_bookkeeping(id) || keep(*id)
+        })
+    }
+
+    /// Swaps every stored plugin of self with those of other, without moving
+    /// any plugin values. The observer, stats and registrations are swapped
+    /// with them.
+    fn swap_extensions(&mut self, other: &mut Self) {
+        mem::swap(self.extensions_mut(), other.extensions_mut())
+    }
+
+    /// Returns the number of stored plugins.
+    fn len(&self) -> uint {
+        self.extensions().len() - hooks::bookkeeping_len(self.extensions())
+    }
+
+    /// Returns true if no plugins are stored.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the stats of accesses of each plugin through the methods which
+    /// store what they create, or None if no plugin has been accessed yet.
+    #[cfg(feature = "stats")]
+    fn plugin_stats(&self) -> Option<&stats::PluginStats> {
+        raw::find::<stats::StatsKey>(self.extensions())
+    }
+
+    /// Returns the number of times create has been called for T by the
+    /// methods which store what they create, including calls whose values
+    /// were since removed or cleared, and each attempt of get_ref_retry. This
+    /// is T's count of misses, and 0 if T was never accessed.
+    ///
+    /// Calls which store nothing, made by compute_uncached, get_ref_dyn or
+    /// with the no-cache feature, are not counted.
+    #[cfg(feature = "stats")]
+    fn recompute_count<T: Key>(&self) -> uint {
+        self.plugin_stats().map_or(0, |stats| stats.recompute_count::<T>())
+    }
+
+    /// Registers K to be written by snapshot and read by restore, replacing
+    /// any plugin registered under the same tag. Registrations are kept by
+    /// clear.
+    #[cfg(feature = "serde")]
+    fn register_snapshot<K: snapshot::SerializablePlugin>(&mut self) {
+        let registered =
+            raw::get_or_insert_with::<snapshot::SnapshotsKey>(self.extensions_mut(), Vec::new);
+        let tag = <K as snapshot::SerializablePlugin>::tag();
+        registered.retain(|r| r.tag != tag);
+        registered.push(snapshot::registered::<K>());
+    }
+
+    /// Writes the value of every stored, registered plugin as bytes, paired
+    /// with its tag, in the order the plugins were registered.
+    #[cfg(feature = "serde")]
+    fn snapshot(&self) -> Vec<(String, Vec<u8>)> {
+        let map = self.extensions();
+        match raw::find::<snapshot::SnapshotsKey>(map) {
+            Some(registered) => registered.iter().filter_map(|r| {
+                (r.serialize)(map).map(|bytes| (r.tag.to_string(), bytes))
+            }).collect(),
+            None => Vec::new()
+        }
+    }
+
+    /// Stores the values read from a snapshot, as insert does, returning the
+    /// number stored. Values whose tags are not registered on this type, or
+    /// whose bytes are invalid, are skipped.
+    #[cfg(feature = "serde")]
+    fn restore(&mut self, data: Vec<(String, Vec<u8>)>) -> uint {
+        let mut restored = Vec::new();
+        {
+            let map = self.extensions_mut();
+            let registered = match raw::remove::<snapshot::SnapshotsKey>(map) {
+                Some(registered) => registered,
+                None => return 0
+            };
+            for &(ref tag, ref bytes) in data.iter() {
+                match registered.iter().find(|r| r.tag == tag.as_slice()) {
+                    Some(r) => if (r.deserialize)(map, bytes.as_slice()) {
+                        restored.push(r.id);
+                    },
+                    None => ()
+                }
+            }
+            raw::insert::<snapshot::SnapshotsKey>(map, registered);
+        }
+        for id in restored.iter() {
+            versioned::record(self, *id);
+        }
+        restored.len()
+    }
+
+    /// Registers T for copying by extend_from.
+    fn register_clone<T: Key>(&mut self) where T::Value: Clone {
+        clones::register(self.extensions_mut(), clones::cloner::<T>());
+    }
+
+    /// Stores a clone of every fresh value stored in other whose plugin is
+    /// registered on other for copying, as insert does, so they need not be
+    /// created again. Plugins which are not registered are skipped, and will
+    /// be created on their next access as usual.
+    ///
+    /// The registrations are copied too, so self can be extended from in
+    /// turn.
+    fn extend_from(&mut self, other: &Self) {
+        let cloners = match raw::find::<clones::ClonesKey>(other.extensions()) {
+            Some(cloners) => cloners.clone(),
+            None => return
+        };
+        for cloner in cloners.into_iter() {
+            if versioned::is_fresh_id(other, cloner.id)
+                    && (cloner.clone)(other.extensions(), self.extensions_mut()) {
+                versioned::record(self, cloner.id);
+            }
+            clones::register(self.extensions_mut(), cloner);
+        }
+    }
+
+    /// Registers category as the category of T, in place of any other, for
+    /// count_in_category. Categories can be of any type, such as an enum of
+    /// the phases of a request.
+    fn register_plugin_category<T: Key, C: 'static>(&mut self, category: C) {
+        categories::register(self.extensions_mut(), TypeId::of::<T>(), category);
+    }
+
+    /// Returns the number of stored plugins registered with a category equal
+    /// to category. Plugins registered with categories of other types are
+    /// not counted.
+    fn count_in_category<C: PartialEq + 'static>(&self, category: C) -> uint {
+        categories::count(self.extensions(), self.cached_type_ids(), &category)
+    }
+
+    /// Registers T for measuring by plugin_memory_hint.
+    fn register_size_hint<T: Key>(&mut self) where T::Value: SizeHint {
+        sizes::register::<T>(self.extensions_mut());
+    }
+
+    /// Returns an estimate of the number of bytes used by the stored values
+    /// of plugins registered through register_size_hint: the size of each
+    /// value plus its SizeHint::heap_size. Plugins which are not registered
+    /// count as 0, as does the storage itself.
+    fn plugin_memory_hint(&self) -> uint {
+        sizes::total(self.extensions())
+    }
+
+    /// Registers T as a plugin which can be created by the TypeId of its key,
+    /// through get_ref_dyn.
+    fn register_plugin<T: PluginFor<Self>>(&mut self) where Self: 'static {
+        dynamic::register(self.extensions_mut(), TypeId::of::<T>(), dynamic::creator::<T, Self>());
+    }
+
+    /// Registers create as the creator of the plugin whose key has the
+    /// TypeId id, for get_ref_dyn, in place of any other.
+    ///
+    /// The value returned by create is stored under id, so it must be of
+    /// that key's value type for get_ref and the other static methods to
+    /// find it; debug builds panic when they find a value of another type.
+    fn register_dyn(&mut self, id: TypeId, create: fn(&Self) -> Option<Box<Any + 'static>>)
+    where Self: 'static {
+        dynamic::register(self.extensions_mut(), id, dynamic::Creator::Dynamic(create));
+    }
+
+    /// Returns true if T is registered with register_plugin or register_dyn.
+    /// This never calls create.
+    fn can_create<T: Key>(&self) -> bool {
+        raw::find::<dynamic::CreatorsKey>(self.extensions())
+            .map_or(false, |creators| creators.contains_key(&TypeId::of::<T>()))
+    }
+
+    /// Creates, stores and returns reference of the value of the registered
+    /// plugin whose key has the TypeId id, as get_ref does, or None if
+    /// construction fails or no such plugin is registered.
+    ///
+    /// A value stored under id is returned even if its plugin isn't
+    /// registered.
+    fn get_ref_dyn(&mut self, id: TypeId) -> Option<&(Any + 'static)> where Self: 'static {
+        let found = versioned::is_fresh_id(self, id);
+        if !found && !dynamic::create(self, id) {
+            return None;
+        }
+        self.extensions().as_raw().get(&id).map(|value| &**value)
+    }
+
+    /// Sets the observer told about every plugin created and stored from now
+    /// on, returning the previous observer, if any.
+    fn set_observer(&mut self, observer: Box<PluginObserver + 'static>)
+            -> Option<Box<PluginObserver + 'static>> {
+        raw::insert::<hooks::ObserverKey>(self.extensions_mut(), observer)
+    }
+
+    /// Removes and returns the observer, if any.
+    fn remove_observer(&mut self) -> Option<Box<PluginObserver + 'static>> {
+        raw::remove::<hooks::ObserverKey>(self.extensions_mut())
+    }
+
+    /// Returns an iterator over the TypeIds of the keys of every stored
+    /// plugin, in no particular order, or with the ordered feature, in the
+    /// order they were first stored.
+    fn cached_type_ids(&self) -> TypeIds {
+        raw::type_ids(self.extensions())
+    }
+
+    /// Returns a printable list of the stored plugins, as cached_type_ids,
+    /// by the type names of their keys. Plugins need not implement Show.
+    fn debug_plugins(&self) -> PluginDebug {
+        debug::new(self.cached_type_ids(), self.extensions())
+    }
+
+    /// Begins a scope which derefs to this type, and removes every plugin
+    /// stored during it when it is dropped.
+    fn scope(&mut self) -> PluginScope<Self> {
+        scope::begin(self)
+    }
+
+    /// Limits the number of plugins stored by the methods which create them
+    /// to n, evicting the least recently accessed of them when another is
+    /// stored. An evicted plugin is created again on its next access.
+    ///
+    /// Only accesses through those methods are tracked, so plugins stored
+    /// through insert and the like, or before the first capacity is set, are
+    /// never evicted. Panics if n is 0.
+    fn set_capacity(&mut self, n: uint) {
+        assert!(n > 0, "set_capacity needs a capacity of at least 1");
+        lru::set_capacity(self.extensions_mut(), n);
+    }
+
+    /// Freezes self, so the methods which create plugins return stored values
+    /// but no longer call create for missing ones, returning None instead.
+    /// Use it to catch plugins created after a setup phase has finished.
+    ///
+    /// get_ref_result and the other methods returning the error of create
+    /// are unaffected, as they have no error of their own to return. Nor are
+    /// the compute methods of Get, which store nothing in self.
+    ///
+    /// The frozen state is a bookkeeping entry in the extension storage, so
+    /// it is kept by clear.
+    fn freeze(&mut self) {
+        hooks::set_frozen(self.extensions_mut(), true)
+    }
+
+    /// Undoes freeze, so missing plugins are created again.
+    fn unfreeze(&mut self) {
+        hooks::set_frozen(self.extensions_mut(), false)
+    }
+
+    /// Returns true if self is frozen.
+    fn is_frozen(&self) -> bool {
+        hooks::is_frozen(self.extensions())
+    }
+
+    /// Returns true if a call of create_mut through get_ref_dependent or
+    /// get_mut_dependent panicked, possibly leaving the stored plugins
+    /// inconsistent, and clear_poison has not been called since.
+    ///
+    /// While self is poisoned, the methods which create plugins return None,
+    /// even for stored values, and try_get_ref returns GetError::Poisoned.
+    /// As with freeze, get_ref_result and the other methods returning the
+    /// error of create, among them try_with, are unaffected, as they have no
+    /// error of their own to return. Only create_mut can poison self, as only
+    /// it can change the stored plugins partway through creating a value.
+    fn is_poisoned(&self) -> bool {
+        hooks::is_poisoned(self.extensions())
+    }
+
+    /// Marks self as no longer poisoned, once the stored plugins are known
+    /// to be consistent, for example after clear.
+    fn clear_poison(&mut self) {
+        hooks::clear_poison(self.extensions_mut())
+    }
+
+    /// Reserves capacity for at least additional more plugins to be stored
+    /// without reallocating.
+    fn reserve(&mut self, additional: uint) {
+        self.extensions_mut().reserve(additional)
+    }
+
+    /// Shrinks the capacity of the extension storage as much as possible,
+    /// such as after retain or many calls of remove. This may reallocate the
+    /// storage, so it is not free; call it between uses of a long-lived
+    /// value rather than on every access.
+    fn shrink_to_fit(&mut self) {
+        self.extensions_mut().shrink_to_fit()
     }
-}
 
+    /// Creates, stores and returns reference of T's value as get_ref does,
+    /// but also remembers when construction fails, so create is not called
+    /// again for T until clear_none is called, or the generation or content
+    /// hash of self changes.
+    ///
+    /// The failures of every plugin are remembered in one bookkeeping entry.
+    fn get_ref_cached_none<T: PluginFor<Self>>(&mut self) -> Option<&T::Value> {
+        match access::lookup::<T, T, Self>(self) {
+            Lookup::Fresh => return raw::find::<T>(self.extensions()),
+            Lookup::Unavailable => return None,
+            Lookup::Missing => ()
+        }
+        let id = TypeId::of::<T>();
+        if versioned::is_fresh::<NotCreated, Self>(self)
+                && raw::find::<NotCreated>(self.extensions()).unwrap().contains(&id) {
+            return None;
+        }
+        let created = access::call::<T, Self, _, _>(self, |: e: &mut Self| {
+            <T as PluginFor<Self>>::create(&*e)
+        });
+        match created {
+            Some(t) => {
+                access::store::<T, T, Self>(self, t);
+                raw::find::<T>(self.extensions())
+            },
+            None => {
+                // Failures remembered at another generation or content hash
+                // are forgotten along with the set holding them.
+                if versioned::is_fresh::<NotCreated, Self>(self) {
+                    raw::find_mut::<NotCreated>(self.extensions_mut()).unwrap().insert(id);
+                } else {
+                    let mut failed = HashSet::new();
+                    failed.insert(id);
+                    versioned::insert::<NotCreated, Self>(self, failed);
+                }
+                None
+            }
+        }
+    }
+
+    /// Forgets a remembered failure to construct T, returning true if one
+    /// was remembered.
+    fn clear_none<T: Key>(&mut self) -> bool {
+        let id = TypeId::of::<T>();
+        versioned::is_fresh::<NotCreated, Self>(self)
+            && raw::find_mut::<NotCreated>(self.extensions_mut()).unwrap().remove(&id)
+    }
+
+    /// Creates and stores the values of two different plugins A and B as
+    /// get_mut does, returning mutable refs of both at once. Each is None
+    /// where get_mut would return None, such as for a stale value while self
+    /// is frozen, and both are None while self is poisoned.
+    ///
+    /// Storing B never evicts A, so with a capacity of 1 both are stored
+    /// until the next access evicts one.
+    ///
+    /// If A and B are the same plugin, both are None and nothing is created,
+    /// as the two refs would alias.
+    fn get_mut_pair<A: PluginFor<Self>, B: PluginFor<Self>>(&mut self)
+            -> (Option<&mut A::Value>, Option<&mut B::Value>) {
+        if TypeId::of::<A>() == TypeId::of::<B>() {
+            return (None, None);
+        }
+        self.precompute::<A>();
+        lru::pin(self.extensions_mut(), Some(TypeId::of::<A>()));
+        self.precompute::<B>();
+        lru::pin(self.extensions_mut(), None);
+        if hooks::is_poisoned(self.extensions()) {
+            return (None, None);
+        }
+        let a = versioned::is_fresh::<A, Self>(self);
+        let b = versioned::is_fresh::<B, Self>(self);
+        let map: *mut AnyMap = self.extensions_mut();
+        debug_assert!(TypeId::of::<A>() != TypeId::of::<B>());
+        // SAFETY: A and B are different keys, checked above, so they are
+        // stored in different entries, and finding an entry doesn't move the
+        // others, so the two refs never alias. Both borrow self mutably, so
+        // nothing else can reach the map while they live.
+        unsafe {
+            (if a { raw::find_mut::<A>(&mut *map) } else { None },
+             if b { raw::find_mut::<B>(&mut *map) } else { None })
+        }
+    }
+
+    /// Removes and returns the value stored under T, creating one through
+    /// T's implementation of create if none is stored. Unlike remove, this
+    /// returns None only if construction fails, or get_ref would not call
+    /// create. Either way, no value is stored under T afterwards.
+    fn take<T: PluginFor<Self>>(&mut self) -> Option<T::Value> {
+        match access::lookup::<T, T, Self>(self) {
+            Lookup::Fresh => return self.remove::<T>(),
+            Lookup::Unavailable => return None,
+            Lookup::Missing => ()
+        }
+        self.remove::<T>();
+        access::call::<T, Self, _, _>(self, |: e: &mut Self| <T as PluginFor<Self>>::create(&*e))
+    }
+
+    /// Stores value under T, returning the value it replaced: None if no
+    /// value was stored under T, Some otherwise.
+    fn replace<T: Key>(&mut self, value: T::Value) -> Option<T::Value> {
+        self.insert::<T>(value)
+    }
+
+    /// Removes the value stored under T, passes it to f, and stores the value
+    /// f returns under T, if any. f gets None if no fresh value is stored,
+    /// and no value is stored under T afterwards if it returns None. This
+    /// never calls create.
+    fn replace_with<T: Key, F: FnOnce(Option<T::Value>) -> Option<T::Value>>(&mut self, f: F) {
+        let fresh = versioned::is_fresh::<T, Self>(self);
+        let old = self.remove::<T>();
+        match f(if fresh { old } else { None }) {
+            Some(t) => { versioned::insert::<T, Self>(self, t); },
+            None => ()
+        }
+    }
+
+    /// Returns a mutable ref of the value stored under T, first storing the
+    /// result of f if no value is stored under T. Unlike create, f cannot
+    /// fail, and T need not be a plugin.
+    fn get_or_insert_with<T: Key, F: FnOnce(&Self) -> T::Value>(&mut self, f: F) -> &mut T::Value {
+        let found = versioned::is_fresh::<T, Self>(self);
+        if !found {
+            let t = f(&*self);
+            versioned::insert::<T, Self>(self, t);
+        }
+        raw::find_mut::<T>(self.extensions_mut()).unwrap()
+    }
+
+    /// Returns the entry of T, which is Occupied if a fresh value is stored
+    /// under T, and Vacant otherwise, so the value can be looked at and
+    /// changed, or stored, in one borrow. This never calls create.
+    fn plugin_entry<T: Key>(&mut self) -> PluginEntry<Self, T> {
+        entry::new::<T, Self>(self)
+    }
+
+    /// Returns a reference of the value stored under T, first storing value
+    /// if no value is stored under T. Unlike insert, this never replaces a
+    /// stored value: if one is stored, value is dropped.
+    fn get_or_provide<T: Key>(&mut self, value: T::Value) -> &T::Value {
+        &*self.get_or_insert_with::<T, _>(move |: _: &Self| value)
+    }
+
+    /// Returns a reference of the value stored under T, first storing the
+    /// default value if no value is stored under T.
+    ///
+    /// This never calls create, even if T is a plugin, but returns a value
+    /// created by an earlier access of T.
+    fn get_default<T: Key>(&mut self) -> &T::Value where T::Value: Default {
+        &*self.get_or_insert_with::<T, _>(|: _: &Self| Default::default())
+    }
+
+    /// Returns a mutable ref of the value stored under T, first storing the
+    /// default value if no value is stored under T. Like get_default, this
+    /// never calls create.
+    fn get_mut_or_default<T: Key>(&mut self) -> &mut T::Value where T::Value: Default {
+        self.get_or_insert_with::<T, _>(|: _: &Self| Default::default())
+    }
+
+    /// Creates, stores and returns a shared pointer to T's value if
+    /// construction through T's implementation of create succeeds, otherwise
+    /// None. Unlike get, this never clones the value itself.
+    ///
+    /// The shared value is stored separately from the value stored by
+    /// get_ref, get_mut and get, so each calls create once. Changing the
+    /// shared value requires replacing it with insert_rc, or copying it on
+    /// write as Rc::make_unique does.
+    fn get_rc<T: PluginFor<Self>>(&mut self) -> Option<Rc<T::Value>> {
+        let stored = access::create_and_store::<T, RcKey<T>, Self, _>(self, |: e: &mut Self| {
+            <T as PluginFor<Self>>::create(&*e).map(|t| Rc::new(t))
+        });
+        if stored { raw::find::<RcKey<T>>(self.extensions()).map(|rc| rc.clone()) } else { None }
+    }
+
+    /// Stores value as the shared value of T, as returned by get_rc, without
+    /// calling create, returning the previously stored shared value, if any.
+    fn insert_rc<T: Key>(&mut self, value: Rc<T::Value>) -> Option<Rc<T::Value>> {
+        versioned::insert::<RcKey<T>, Self>(self, value)
+    }
+
+    /// Returns a shared pointer to T's value, creating it through T's
+    /// implementation of create if it is not alive, otherwise None.
+    ///
+    /// Only a weak pointer is stored, so the value is dropped once every
+    /// returned pointer is, and is created again on its next access. Keep
+    /// the returned pointer for as long as the value is used. Weak values
+    /// are stored separately from those of get_rc and get_ref.
+    fn get_weak<T: PluginFor<Self>>(&mut self) -> Option<Rc<T::Value>> {
+        let alive = raw::find::<WeakKey<T>>(self.extensions()).and_then(|weak| weak.upgrade());
+        if alive.is_none() {
+            // The value was dropped, so only its dangling pointer is stored.
+            raw::remove::<WeakKey<T>>(self.extensions_mut());
+        }
+        match access::lookup::<T, WeakKey<T>, Self>(self) {
+            Lookup::Fresh => return alive,
+            Lookup::Unavailable => return None,
+            Lookup::Missing => ()
+        }
+        let t = Rc::new(try_option!(access::call::<T, Self, _, _>(self, |: e: &mut Self| {
+            <T as PluginFor<Self>>::create(&*e)
+        })));
+        access::store::<T, WeakKey<T>, Self>(self, t.downgrade());
+        Some(t)
+    }
+
+    /// Creates, stores and returns reference of T's value if construction
+    /// through T's implementation of create_mut succeeds, otherwise None.
+    ///
+    /// If a value is stored under T while create_mut runs, for example by a
+    /// reentrant call, that value is kept and the one returned by create_mut
+    /// is dropped. If creating T requires getting T through this method
+    /// again, directly or through other plugins, the inner get returns None
+    /// rather than recursing forever.
+    fn get_ref_dependent<T: PluginForMut<Self>>(&mut self) -> Option<&T::Value> {
+        match access::lookup::<T, T, Self>(self) {
+            Lookup::Fresh => return raw::find::<T>(self.extensions()),
+            Lookup::Unavailable => return None,
+            Lookup::Missing => ()
+        }
+        if !hooks::begin_creation::<T, Self>(self) {
+            return None;
+        }
+        let created = access::call::<T, Self, _, _>(self, |: e: &mut Self| {
+            let guard = hooks::poison_guard(e.extensions_mut());
+            let created = <T as PluginForMut<Self>>::create_mut(e);
+            guard.disarm();
+            created
+        });
+        hooks::end_creation::<T, Self>(self);
+        let t = try_option!(created);
+        if !versioned::is_fresh::<T, Self>(self) {
+            access::store::<T, T, Self>(self, t);
+        }
+        raw::find::<T>(self.extensions())
+    }
+
+    /// Creates, stores and returns reference of T's value if construction
+    /// through T's implementation of create_with_ctx succeeds, otherwise None.
+    ///
+    /// Values which create_with_ctx stores through its context are stored at
+    /// once, so before T's value is, and are kept even if it fails.
+    fn get_ref_ctx<T: PluginForCtx<Self>>(&mut self) -> Option<&T::Value> {
+        let stored = access::create_and_store::<T, T, Self, _>(self, |: e: &mut Self| {
+            <T as PluginForCtx<Self>>::create_with_ctx(&mut context::new(e))
+        });
+        if stored { raw::find::<T>(self.extensions()) } else { None }
+    }
+
+    /// Creates, stores and returns a mutable ref of T's value if construction
+    /// through T's implementation of create_mut succeeds, otherwise None.
+    ///
+    /// Reentrant and cyclic creation are handled as in get_ref_dependent.
+    fn get_mut_dependent<T: PluginForMut<Self>>(&mut self) -> Option<&mut T::Value> {
+        match access::lookup::<T, T, Self>(self) {
+            Lookup::Fresh => return raw::find_mut::<T>(self.extensions_mut()),
+            Lookup::Unavailable => return None,
+            Lookup::Missing => ()
+        }
+        if !hooks::begin_creation::<T, Self>(self) {
+            return None;
+        }
+        let created = access::call::<T, Self, _, _>(self, |: e: &mut Self| {
+            let guard = hooks::poison_guard(e.extensions_mut());
+            let created = <T as PluginForMut<Self>>::create_mut(e);
+            guard.disarm();
+            created
+        });
+        hooks::end_creation::<T, Self>(self);
+        let t = try_option!(created);
+        if !versioned::is_fresh::<T, Self>(self) {
+            access::store::<T, T, Self>(self, t);
+        }
+        raw::find_mut::<T>(self.extensions_mut())
+    }
+}
+
+/// Implementations of this trait can act as plugins for `T` which depend on
+/// other plugins for `T`, via `T::get_ref_dependent<P>()`.
+///
+/// As create_mut gets a mutable ref, it can also move part of T into the
+/// plugin's value, for example by calling take on an Option field. Once the
+/// value is stored, get_ref_dependent returns it without calling create_mut
+/// again, so the part is only moved once; but it is called again if the
+/// value is removed, and must then cope with the part being gone.
+pub trait PluginForMut<T>: Key {
+    /// Create the value of Self from an instance of T, which may be used to
+    /// get other plugins. This will be called only once.
+    fn create_mut(&mut T) -> Option<Self::Value>;
+}
+
+/// Implementations of this trait can act as plugins for `T` which are created
+/// from arguments chosen by the caller, via `T::get_with<P, A>(args)`.
+pub trait PluginForArgs<T, A>: Key {
+    /// Create the value of Self from an instance of T and args.
+    fn create(&T, args: A) -> Option<Self::Value>;
+}
+
+/// Implementations of this trait can act as plugins for `T` which store
+/// other plugins while they are created, via `T::get_ref_ctx<P>()`.
+pub trait PluginForCtx<T>: Key {
+    /// Create the value of Self from the source of ctx, storing any other
+    /// plugins through ctx. This will be called only once.
+    fn create_with_ctx(ctx: &mut PluginContext<T>) -> Option<Self::Value>;
+}
+
+/// The key under which get_ref_cached_none stores the TypeIds of the keys of
+/// the plugins whose construction failed.
+struct NotCreated;
+
+impl Key for NotCreated { type Value = HashSet<TypeId>; }
+
+bookkeeping!(NotCreated)
+
+/// An observer of the plugins created for an extensible type.
+pub trait PluginObserver {
+    /// Called after a value is created and stored for the plugin whose key
+    /// has the TypeId id. The observer can't reach the extensible type, so
+    /// it can't change the stored plugins while it is called.
+    fn created(&self, id: TypeId);
+
+    /// Called with the TypeId of the key of a plugin after its value is
+    /// removed, by remove, clear, retain, a scope or eviction. Values
+    /// removed by remove are still alive, as they are returned; the others
+    /// have already been dropped. Does nothing by default.
+    fn removed(&self, _: TypeId) {}
+}
+
+/// The key under which get_rc stores the shared value of T.
+struct RcKey<T>;
+
+impl<T: Key> Key for RcKey<T> { type Value = Rc<T::Value>; }
+
+/// The key under which get_weak stores the weak value of T.
+struct WeakKey<T>;
+
+impl<T: Key> Key for WeakKey<T> { type Value = Weak<T::Value>; }
+
+/// The key under which get_ref_named stores the named values of T.
+struct Named<T>;
+
+impl<T: Key> Key for Named<T> { type Value = HashMap<&'static str, T::Value>; }
+
+/// The key under which get_ref_by_key stores the values of T for each K.
+struct ByKey<K, T>;
+
+impl<K: Hash + Eq + 'static, T: 'static> Key for ByKey<K, T> { type Value = HashMap<K, T>; }
+
+/// An interface for getting plugins on non-extensible types.
+pub trait Get {
+    /// Call the appropriate PluginFor implementation to create an instance
+    /// of T's value. Nothing is cached, so create is called every time.
+    fn compute<T: PluginFor<Self>>(&self) -> Option<T::Value> {
+        <T as PluginFor<Self>>::create(self)
+    }
+
+    /// Call the appropriate PluginFor implementation to create an instance
+    /// of T's value into buf, in place of any previous value, through
+    /// create_into. Repeated computations reuse buf's allocation only if T
+    /// overrides create_into to do so.
+    fn compute_into<T: PluginFor<Self>>(&self, buf: &mut Option<T::Value>) {
+        <T as PluginFor<Self>>::create_into(self, buf)
+    }
+
+    /// Call the appropriate PluginFor implementation to create an instance
+    /// of T's value, unless one is already stored under T in cache, and
+    /// return the value stored in cache. Failed creations are not stored.
+    ///
+    /// The cache can outlive self, such as a map kept per connection while
+    /// self is a request, so the reference returned borrows cache, not self.
+    fn compute_cached_in<'a, T: PluginFor<Self>>(&self, cache: &'a mut AnyMap)
+            -> Option<&'a T::Value> {
+        if !raw::contains::<T>(cache) {
+            let t = try_option!(<T as PluginFor<Self>>::create(self));
+            raw::insert::<T>(cache, t);
+        }
+        raw::find::<T>(cache)
+    }
+
+    /// Call the appropriate PluginFor implementation to create an instance
+    /// of T's value, unless one is already cached on this thread, and return
+    /// the cached value.
+    ///
+    /// The cache is separate from any extension storage and keyed by T
+    /// alone, so the value is shared by every source on the thread, of any
+    /// type; use it for plugins which don't depend on their source. Values
+    /// live until the thread exits or clear_thread_cache is called on it,
+    /// and failed creations are not cached.
+    fn compute_thread_cached<T: PluginFor<Self>>(&self) -> Option<Rc<T::Value>> {
+        match thread_cache::find::<T>() {
+            Some(t) => return Some(t),
+            None => ()
+        }
+        let t = try_option!(<T as PluginFor<Self>>::create(self));
+        Some(thread_cache::insert::<T>(Rc::new(t)))
+    }
+
+    /// Call the appropriate PluginForArgs implementation to create an
+    /// instance of T's value from args. Nothing is cached, so create is
+    /// called every time.
+    fn compute_with<T: PluginForArgs<Self, A>, A>(&self, args: A) -> Option<T::Value> {
+        <T as PluginForArgs<Self, A>>::create(self, args)
+    }
+
+    /// Call the PluginFor implementation for U, the source self dereferences
+    /// to, to create an instance of T's value, so a plugin for U also serves
+    /// a reference, Box or Rc of U.
+    ///
+    /// Nothing is cached, as self is not the extensible type; get cached
+    /// values through the U itself, where they are stored once however many
+    /// of its references compute them.
+    fn compute_deref<T: PluginFor<U>, U>(&self) -> Option<T::Value> where Self: Deref<U> {
+        <T as PluginFor<U>>::create(self.deref())
+    }
+
+    /// Call the appropriate PluginForBorrowed implementation to create a view
+    /// of T which borrows from self. Nothing is cached, as only values which
+    /// don't borrow can be.
+    fn compute_borrowed<'a, T: PluginForBorrowed<'a, Self>>(&'a self)
+            -> Option<<T as PluginForBorrowed<'a, Self>>::View> {
+        <T as PluginForBorrowed<'a, Self>>::create_borrowed(self)
+    }
+}
+
+impl<T> Get for T {}
+impl<T: Extensible> GetCached for T {}
+
+/// Expose an interface for cacheing fallible plugins on an extensible type
+/// with a single error type, into which the errors of every plugin convert.
+///
+/// This is not implemented for every extensible type, as each chooses its
+/// own error. The values are stored as get_ref_result stores them, so they
+/// are also returned by the Option-based methods of GetCached.
+pub trait FallibleGetCached: GetCached {
+    /// The error returned when construction of any plugin fails.
+    type Error;
+
+    /// Creates, stores and returns a reference of T's value as
+    /// get_ref_result does, converting any error to Self::Error.
+    fn get_ref_fallible<T>(&mut self) -> Result<&T::Value, Self::Error>
+    where T: PluginForResult<Self>, Self::Error: FromError<<T as PluginForResult<Self>>::Error> {
+        self.get_ref_result::<T>().map_err(FromError::from_error)
+    }
+
+    /// Creates, stores and returns a mutable ref of T's value as
+    /// get_mut_result does, converting any error to Self::Error.
+    fn get_mut_fallible<T>(&mut self) -> Result<&mut T::Value, Self::Error>
+    where T: PluginForResult<Self>, Self::Error: FromError<<T as PluginForResult<Self>>::Error> {
+        self.get_mut_result::<T>().map_err(FromError::from_error)
+    }
+
+    /// Creates, stores and returns an instance of T's value as get_result
+    /// does, converting any error to Self::Error.
+    fn get_fallible<T>(&mut self) -> Result<T::Value, Self::Error>
+    where T: PluginForResult<Self>, T::Value: Clone,
+          Self::Error: FromError<<T as PluginForResult<Self>>::Error> {
+        self.get_result::<T>().map_err(FromError::from_error)
+    }
+}
+
+/// A type under which a plugin's value is stored.
+///
+/// The key may be the value itself, or a separate, usually zero-sized, type,
+/// so that several plugins can produce values of the same type.
+pub trait Key: 'static {
+    /// The type of the value stored under this key.
+    type Value: 'static;
+}
+
+/// Implementations of this trait can act as plugins for `T`, via `T::get<P>()`
+pub trait PluginFor<T>: Key {
+    /// Create the value of Self from an instance of T. This will be called
+    /// only once.
+    fn create(&T) -> Option<Self::Value>;
+
+    /// Create the value of Self from an instance of T into buf, for
+    /// compute_into. Override this to reuse the allocation of the value
+    /// already in buf; by default it is replaced by the result of create.
+    fn create_into(t: &T, buf: &mut Option<Self::Value>) {
+        *buf = <Self as PluginFor<T>>::create(t);
+    }
+}
+
+/// A plugin whose value can be returned by value, through get.
+///
+/// This is implemented for every plugin whose value is Clone; a plugin whose
+/// value is not can still be borrowed through get_ref.
+pub trait CloneablePlugin<T>: PluginFor<T> {
+    /// Clone a value of Self.
+    fn clone_value(value: &Self::Value) -> Self::Value;
+}
+
+impl<T, P> CloneablePlugin<T> for P where P: PluginFor<T>, P::Value: Clone {
+    fn clone_value(value: &P::Value) -> P::Value { value.clone() }
+}
+
+/// Implementations of this trait can act as plugins for `T` whose values
+/// borrow from `T` for the lifetime `'a`, via `T::compute_borrowed<P>()`.
+/// Their values are never cached.
+pub trait PluginForBorrowed<'a, T> {
+    /// The type of the view, which may borrow from T.
+    type View;
+
+    /// Create the view of Self from an instance of T.
+    fn create_borrowed(&'a T) -> Option<Self::View>;
+}
+
+/// A key whose value can be made from the value of U, so U can stand in for
+/// it through get_ref_or_alt.
+pub trait FallbackFrom<U: Key>: Key {
+    /// Convert a value of U to a value of Self.
+    fn convert(value: U::Value) -> Self::Value;
+}
+
+/// Implementations of this trait can act as fallible plugins for `T`, via
+/// `T::get_result<P>()`.
+pub trait PluginForResult<T>: Key {
+    /// The reason construction of Self failed.
+    type Error;
+
+    /// Create the value of Self from an instance of T, or explain why it
+    /// could not be created. This will be called until it succeeds once.
+    fn create(&T) -> Result<Self::Value, Self::Error>;
+}
+
+#[cfg(test)]
+mod test {
+    use anymap::AnyMap;
+    use super::{Extensible, Key, PluginFor, PluginForResult, PluginForMut, PluginForArgs};
+    use super::{BoxedPlugin, FallbackFrom, IntoExtensible, PluginContext, PluginForBorrowed};
+    use super::{PluginForCtx, PluginObserver};
+    use super::{FallibleGetCached, Get, GetCached, GetError, PluginEntry, PluginError};
+    use super::{PluginCow, SizeHint, clear_thread_cache};
+    use std::any::{Any, AnyRefExt};
+    use std::cell::{Cell, RefCell};
+    use std::error::FromError;
+    use std::hash::hash;
+    use std::intrinsics::TypeId;
+    use std::mem;
+    use std::rc::Rc;
+    use std::rt::unwind;
+    use hooks;
+    use raw;
+    use versioned;
+    use test::Bencher;
+
+    struct Extended {
+        map: AnyMap
+    }
+
+    impl Extended {
+        fn new() -> Extended {
+            Extended { map: AnyMap::new() }
+        }
+    }
+
+    impl Extensible for Extended {
+        fn extensions(&self) -> &AnyMap { &self.map }
+        fn extensions_mut(&mut self) -> &mut AnyMap { &mut self.map }
+    }
+
+    macro_rules! generate_plugin (
+        ($t:ty, $v:ident, $v2:expr) => {
+            #[deriving(PartialEq, Show, Clone)]
+            struct $v(uint);
+
+            impl Key for $t { type Value = $t; }
+
+            impl PluginFor<Extended> for $t {
+                fn create(_: &Extended) -> Option<$t> { Some($v($v2)) }
+            }
+        }
+    )
+
+    generate_plugin!(One, One, 1)
+    generate_plugin!(Two, Two, 2)
+    generate_plugin!(Three, Three, 3)
+    generate_plugin!(Four, Four, 4)
+    generate_plugin!(Five, Five, 5)
+    generate_plugin!(Six, Six, 6)
+    generate_plugin!(Seven, Seven, 7)
+    generate_plugin!(Eight, Eight, 8)
+    generate_plugin!(Nine, Nine, 9)
+    generate_plugin!(Ten, Ten, 10)
+
+    #[deriving(PartialEq, Show, Clone)]
+    struct Missing(uint);
+
+    impl Key for Missing { type Value = Missing; }
+
+    impl PluginFor<Extended> for Missing {
+        fn create(_: &Extended) -> Option<Missing> { None }
+    }
+
+    #[deriving(PartialEq, Show, Clone)]
+    struct Tripled(uint);
+
+    impl Key for Tripled { type Value = Tripled; }
+
+    // Only succeeds once One has been cached.
+    impl PluginFor<Extended> for Tripled {
+        fn create(extended: &Extended) -> Option<Tripled> {
+            extended.extensions().find::<One>().map(|&One(n)| Tripled(n * 3))
+        }
+    }
+
+    #[deriving(PartialEq, Show, Clone)]
+    struct Sum(uint);
+
+    impl Key for Sum { type Value = Sum; }
+
+    impl PluginForMut<Extended> for Sum {
+        fn create_mut(extended: &mut Extended) -> Option<Sum> {
+            let One(a) = try_option!(extended.get::<One>());
+            let Two(b) = try_option!(extended.get::<Two>());
+            Some(Sum(a + b))
+        }
+    }
+
+    #[deriving(PartialEq, Show, Clone)]
+    struct Reentrant(uint);
+
+    impl Key for Reentrant { type Value = Reentrant; }
+
+    // Stores itself during creation, as a reentrant get would.
+    impl PluginForMut<Extended> for Reentrant {
+        fn create_mut(extended: &mut Extended) -> Option<Reentrant> {
+            extended.insert::<Reentrant>(Reentrant(1));
+            Some(Reentrant(2))
+        }
+    }
+
+    // Ping and Pong each depend on the other.
+    struct Ping;
+
+    impl Key for Ping { type Value = uint; }
+
+    impl PluginForMut<Extended> for Ping {
+        fn create_mut(extended: &mut Extended) -> Option<uint> {
+            extended.get_ref_dependent::<Pong>().map(|n| *n + 1)
+        }
+    }
+
+    struct Pong;
+
+    impl Key for Pong { type Value = uint; }
+
+    impl PluginForMut<Extended> for Pong {
+        fn create_mut(extended: &mut Extended) -> Option<uint> {
+            extended.get_mut_dependent::<Ping>().map(|n| *n + 1)
+        }
+    }
+
+    #[deriving(PartialEq, Show, Clone)]
+    struct Doubled(uint);
+
+    impl Key for Doubled { type Value = Doubled; }
+
+    // Only succeeds once One has been cached.
+    impl PluginForResult<Extended> for Doubled {
+        type Error = &'static str;
+
+        fn create(extended: &Extended) -> Result<Doubled, &'static str> {
+            match extended.extensions().find::<One>() {
+                Some(&One(n)) => Ok(Doubled(n * 2)),
+                None => Err("One is not cached")
+            }
+        }
+    }
+
+    // Keys which are not plugins, storing values of other types.
+    struct Note;
+
+    impl Key for Note { type Value = &'static str; }
+
+    struct Count;
+
+    impl Key for Count { type Value = uint; }
+
+    // Two plugins producing values of the same type.
+    struct FirstName;
+
+    impl Key for FirstName { type Value = String; }
+
+    impl PluginFor<Extended> for FirstName {
+        fn create(_: &Extended) -> Option<String> { Some("Jonathan".to_string()) }
+    }
+
+    struct LastName;
+
+    impl Key for LastName { type Value = String; }
+
+    impl PluginFor<Extended> for LastName {
+        fn create(_: &Extended) -> Option<String> { Some("Reem".to_string()) }
+    }
+
+    struct Derived {
+        extensions: AnyMap
+    }
+
+    impl_extensible!(Derived, extensions)
+
+    impl PluginFor<Derived> for One {
+        fn create(_: &Derived) -> Option<One> { Some(One(1)) }
+    }
+
+    struct Versioned {
+        map: AnyMap,
+        generation: u64
+    }
+
+    impl Extensible for Versioned {
+        fn extensions(&self) -> &AnyMap { &self.map }
+        fn extensions_mut(&mut self) -> &mut AnyMap { &mut self.map }
+        fn generation(&self) -> u64 { self.generation }
+    }
+
+    struct Text {
+        text: String,
+        map: AnyMap
+    }
+
+    impl Extensible for Text {
+        fn extensions(&self) -> &AnyMap { &self.map }
+        fn extensions_mut(&mut self) -> &mut AnyMap { &mut self.map }
+        fn content_hash(&self) -> Option<u64> { Some(hash(&self.text)) }
+    }
+
+    struct Length;
+
+    impl Key for Length { type Value = uint; }
+
+    impl PluginFor<Text> for Length {
+        fn create(text: &Text) -> Option<uint> { Some(text.text.len()) }
+    }
+
+    // Records the generation it was created at.
+    struct CreatedAt;
+
+    impl Key for CreatedAt { type Value = u64; }
+
+    impl PluginFor<Versioned> for CreatedAt {
+        fn create(versioned: &Versioned) -> Option<u64> { Some(versioned.generation) }
+    }
+
+    struct NextGeneration;
+
+    impl Key for NextGeneration { type Value = u64; }
+
+    impl PluginFor<Versioned> for NextGeneration {
+        fn create(versioned: &Versioned) -> Option<u64> { Some(versioned.generation + 1) }
+    }
+
+    // Records every plugin it is told about.
+    struct Recorder {
+        created: Rc<RefCell<Vec<TypeId>>>
+    }
+
+    impl PluginObserver for Recorder {
+        fn created(&self, id: TypeId) {
+            self.created.borrow_mut().push(id);
+        }
+    }
+
+    // A plugin for a non-extensible type.
+    struct Twice;
+
+    impl Key for Twice { type Value = uint; }
+
+    impl PluginFor<uint> for Twice {
+        fn create(n: &uint) -> Option<uint> { Some(*n * 2) }
+    }
+
+    #[deriving(PartialEq, Show, Clone)]
+    struct Formatted(String);
+
+    impl Key for Formatted { type Value = Formatted; }
+
+    impl PluginForArgs<Extended, &'static str> for Formatted {
+        fn create(extended: &Extended, spec: &'static str) -> Option<Formatted> {
+            match spec {
+                "len" => Some(Formatted(format!("{} plugins", extended.len()))),
+                _ => None
+            }
+        }
+    }
+
+    struct Attempts;
+
+    impl Key for Attempts { type Value = Cell<uint>; }
+
+    struct Flaky;
+
+    impl Key for Flaky { type Value = uint; }
+
+    // Succeeds on the third attempt, counting attempts under Attempts.
+    impl PluginFor<Extended> for Flaky {
+        fn create(extended: &Extended) -> Option<uint> {
+            let attempts = raw::find::<Attempts>(extended.extensions()).unwrap();
+            attempts.set(attempts.get() + 1);
+            if attempts.get() >= 3 { Some(attempts.get()) } else { None }
+        }
+    }
+
+    plugin_cache!(struct Memo { n: uint })
+
+    struct Halved;
+
+    impl Key for Halved { type Value = uint; }
+
+    impl PluginFor<Memo> for Halved {
+        fn create(memo: &Memo) -> Option<uint> { Some(memo.n / 2) }
+    }
+
+    #[deriving(PartialEq, Show)]
+    struct Parsed(uint);
+
+    impl Key for Parsed { type Value = Parsed; }
+
+    // Stores the number of plugins it saw under Count as it is created.
+    impl PluginForCtx<Extended> for Parsed {
+        fn create_with_ctx(ctx: &mut PluginContext<Extended>) -> Option<Parsed> {
+            let len = ctx.source().len();
+            ctx.insert::<Count>(len);
+            if ctx.contains::<Missing>() { None } else { Some(Parsed(len)) }
+        }
+    }
+
+    struct Request {
+        body: Option<String>,
+        map: AnyMap
+    }
+
+    impl_extensible!(Request, map)
+
+    struct Body;
+
+    impl Key for Body { type Value = String; }
+
+    impl PluginForMut<Request> for Body {
+        fn create_mut(request: &mut Request) -> Option<String> { request.body.take() }
+    }
+
+    trait Renderer {
+        fn render(&self) -> String;
+    }
+
+    struct Upper;
+
+    impl Renderer for Upper {
+        fn render(&self) -> String { "UPPER".to_string() }
+    }
+
+    impl PluginFor<Extended> for BoxedPlugin<Renderer + 'static> {
+        fn create(_: &Extended) -> Option<Box<Renderer + 'static>> {
+            Some(box Upper as Box<Renderer + 'static>)
+        }
+    }
+
+    impl FallbackFrom<One> for Missing {
+        fn convert(One(n): One) -> Missing { Missing(n + 1) }
+    }
+
+    impl FallbackFrom<Three> for Two {
+        fn convert(Three(n): Three) -> Two { Two(n) }
+    }
+
+    struct FirstWord;
+
+    impl<'a> PluginForBorrowed<'a, String> for FirstWord {
+        type View = &'a str;
+
+        fn create_borrowed(text: &'a String) -> Option<&'a str> { text.as_slice().words().next() }
+    }
+
+    #[test] fn test_simple() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_ref::<One>(),   Some(&One(1)))
+        assert_eq!(extended.get_ref::<Two>(),   Some(&Two(2)))
+        assert_eq!(extended.get_ref::<Three>(), Some(&Three(3)))
+    }
+
+    #[test] fn test_resize() {
+        let mut extended = Extended::new();
+        extended.get::<One>();
+        extended.get::<Two>();
+        extended.get::<Three>();
+        extended.get::<Four>();
+        extended.get::<Five>();
+        extended.get::<Six>();
+        extended.get::<Seven>();
+        extended.get::<Eight>();
+        extended.get::<Nine>();
+        extended.get::<Ten>();
+        assert_eq!(extended.get_ref::<One>(), Some(&One(1)))
+    }
+
+    #[test] fn test_reserve() {
+        let mut extended = Extended::new();
+        extended.reserve(10);
+        extended.get::<One>();
+        extended.get::<Two>();
+        extended.get::<Three>();
+        extended.get::<Four>();
+        extended.get::<Five>();
+        extended.get::<Six>();
+        extended.get::<Seven>();
+        extended.get::<Eight>();
+        extended.get::<Nine>();
+        extended.get::<Ten>();
+        assert_eq!(extended.len(), 10)
+        assert_eq!(extended.get_ref::<One>(), Some(&One(1)))
+        assert_eq!(extended.get_ref::<Ten>(), Some(&Ten(10)))
+    }
+
+    #[test] fn test_result() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_ref_result::<Doubled>(), Err("One is not cached"))
+        assert!(!extended.extensions().contains::<Doubled>())
+
+        extended.get_ref::<One>();
+        assert_eq!(extended.get_ref_result::<Doubled>(), Ok(&Doubled(2)))
+        assert_eq!(extended.get_mut_result::<Doubled>(), Ok(&mut Doubled(2)))
+        assert_eq!(extended.get_result::<Doubled>(), Ok(Doubled(2)))
+    }
+
+    #[test] fn test_remove() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.remove::<One>(), None)
+
+        *extended.get_mut::<One>().unwrap() = One(5);
+        assert_eq!(extended.get_ref::<One>(), Some(&One(5)))
+        assert_eq!(extended.remove::<One>(), Some(One(5)))
+        assert!(!extended.extensions().contains::<One>())
+        assert_eq!(extended.get_ref::<One>(), Some(&One(1)))
+    }
+
+    #[test] fn test_insert() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.insert::<One>(One(7)), None)
+        assert_eq!(extended.get_ref::<One>(), Some(&One(7)))
+        assert_eq!(extended.insert::<One>(One(8)), Some(One(7)))
+        assert_eq!(extended.get::<One>(), Some(One(8)))
+
+        extended.insert::<Note>("not a plugin");
+        assert_eq!(raw::find::<Note>(extended.extensions()), Some(&"not a plugin"))
+    }
+
+    #[test] fn test_contains() {
+        let mut extended = Extended::new();
+        assert!(!extended.contains::<One>())
+        extended.get_ref::<One>();
+        assert!(extended.contains::<One>())
+        assert!(!extended.contains::<Two>())
+    }
+
+    #[test] fn test_get_or() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_or::<One>(One(5)), One(1))
+        assert_eq!(extended.get_or::<Missing>(Missing(5)), Missing(5))
+        assert_eq!(extended.get_ref::<Missing>(), Some(&Missing(5)))
+        assert_eq!(extended.get_or::<Missing>(Missing(6)), Missing(5))
+    }
+
+    #[test] fn test_get_or_poisoned() {
+        let mut extended = Extended::new();
+        extended.get_ref::<One>();
+        hooks::poison_guard(extended.extensions_mut());
+        assert_eq!(extended.get_or::<One>(One(5)), One(5))
+        assert_eq!(extended.get_or_else::<One, _>(|:| One(6)), One(6))
+        extended.clear_poison();
+        assert_eq!(extended.get_ref::<One>(), Some(&One(1)))
+    }
+
+    #[test] fn test_get_or_else() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_or_else::<One, _>(|:| One(5)), One(1))
+        assert_eq!(extended.get_or_else::<Missing, _>(|:| Missing(5)), Missing(5))
+        assert_eq!(extended.get_or_else::<Missing, _>(|:| -> Missing { panic!("cached") }), Missing(5))
+    }
+
+    #[test] fn test_precompute() {
+        let mut extended = Extended::new();
+        assert!(extended.precompute::<One>())
+        assert!(extended.contains::<One>())
+        assert!(!extended.precompute::<Missing>())
+        assert!(!extended.contains::<Missing>())
+
+        *extended.get_mut::<One>().unwrap() = One(5);
+        assert!(extended.precompute::<One>())
+        assert_eq!(extended.get_ref::<One>(), Some(&One(5)))
+    }
+
+    #[test] fn test_clear() {
+        let mut extended = Extended::new();
+        *extended.get_mut::<One>().unwrap() = One(5);
+        extended.get_ref::<Two>();
+        extended.get_ref::<Three>();
+
+        extended.clear();
+        assert!(!extended.contains::<One>())
+        assert!(!extended.contains::<Two>())
+        assert!(!extended.contains::<Three>())
+        assert_eq!(extended.get_ref::<One>(), Some(&One(1)))
+    }
+
+    #[test] fn test_len() {
+        let mut extended = Extended::new();
+        assert!(extended.is_empty())
+        assert_eq!(extended.len(), 0)
+
+        extended.get_ref::<One>();
+        extended.get_ref::<Two>();
+        extended.get_ref::<Missing>();
+        assert!(!extended.is_empty())
+        assert_eq!(extended.len(), 2)
+    }
+
+    #[test] fn test_cached_none() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_ref_cached_none::<Tripled>(), None)
+        assert!(extended.is_empty())
+
+        extended.get_ref::<One>();
+        assert_eq!(extended.get_ref_cached_none::<Tripled>(), None)
+
+        assert!(extended.clear_none::<Tripled>())
+        assert!(!extended.clear_none::<Tripled>())
+        assert_eq!(extended.get_ref_cached_none::<Tripled>(), Some(&Tripled(3)))
+    }
+
+    struct AfterFirst;
+
+    impl Key for AfterFirst { type Value = u64; }
+
+    impl PluginFor<Versioned> for AfterFirst {
+        fn create(versioned: &Versioned) -> Option<u64> {
+            if versioned.generation > 1 { Some(versioned.generation) } else { None }
+        }
+    }
+
+    #[test] fn test_cached_none_generation() {
+        let mut versioned = Versioned { map: AnyMap::new(), generation: 1 };
+        assert_eq!(versioned.get_ref_cached_none::<AfterFirst>(), None)
+        versioned.clear();
+        assert_eq!(versioned.get_ref_cached_none::<AfterFirst>(), None)
+        assert!(versioned.is_empty())
+
+        versioned.generation = 2;
+        assert!(!versioned.clear_none::<AfterFirst>())
+        assert_eq!(versioned.get_ref_cached_none::<AfterFirst>(), Some(&2))
+    }
+
+    #[test] fn test_take() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.take::<One>(), Some(One(1)))
+        assert!(!extended.contains::<One>())
+
+        *extended.get_mut::<One>().unwrap() = One(5);
+        assert_eq!(extended.take::<One>(), Some(One(5)))
+        assert!(!extended.contains::<One>())
+
+        assert_eq!(extended.take::<Missing>(), None)
+    }
+
+    #[test] fn test_replace() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.replace::<Two>(Two(5)), None)
+        assert_eq!(extended.replace::<Two>(Two(6)), Some(Two(5)))
+        assert_eq!(extended.get_ref::<Two>(), Some(&Two(6)))
+    }
+
+    #[test] fn test_get_or_insert_with() {
+        let mut extended = Extended::new();
+        extended.get_ref::<One>();
+        assert_eq!(*extended.get_or_insert_with::<Count, _>(|: e: &Extended| e.len()), 1)
+        *extended.get_or_insert_with::<Count, _>(|: _: &Extended| 0u) += 1;
+        assert_eq!(raw::find::<Count>(extended.extensions()), Some(&2))
+    }
+
+    #[test] fn test_dependent() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_ref_dependent::<Sum>(), Some(&Sum(3)))
+        assert!(extended.contains::<One>())
+        assert!(extended.contains::<Two>())
+        assert_eq!(extended.get_mut_dependent::<Sum>(), Some(&mut Sum(3)))
+    }
+
+    #[test] fn test_dependent_reentrant() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_ref_dependent::<Reentrant>(), Some(&Reentrant(1)))
+        assert_eq!(extended.len(), 1)
+    }
+
+    #[test] fn test_cached_type_ids() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.cached_type_ids().count(), 0)
+
+        extended.get_ref::<One>();
+        extended.get_ref::<FirstName>();
+        let mut ids: Vec<TypeId> = extended.cached_type_ids().collect();
+        ids.sort_by(|a, b| a.hash().cmp(&b.hash()));
+        let mut expected = vec![TypeId::of::<One>(), TypeId::of::<FirstName>()];
+        expected.sort_by(|a, b| a.hash().cmp(&b.hash()));
+        assert_eq!(ids, expected)
+    }
+
+    #[test] fn test_get_or_provide() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_or_provide::<One>(One(5)), &One(5))
+        assert_eq!(extended.get_or_provide::<One>(One(6)), &One(5))
+        assert_eq!(extended.get_ref::<One>(), Some(&One(5)))
+    }
+
+    #[test] fn test_get_default() {
+        let mut extended = Extended::new();
+        assert_eq!(*extended.get_default::<Count>(), 0)
+        *extended.get_or_insert_with::<Count, _>(|: _: &Extended| 5) += 1;
+        assert_eq!(*extended.get_default::<Count>(), 1)
+
+        extended.get_ref::<FirstName>();
+        assert_eq!(extended.get_default::<FirstName>().as_slice(), "Jonathan")
+        assert_eq!(extended.get_default::<LastName>().as_slice(), "")
+    }
+
+    #[test] fn test_keys() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_ref::<FirstName>().map(|s| s.as_slice()), Some("Jonathan"))
+        assert_eq!(extended.get_ref::<LastName>().map(|s| s.as_slice()), Some("Reem"))
+        assert_eq!(extended.len(), 2)
+    }
+
+    #[test] fn test_compute() {
+        assert_eq!(3u.compute::<Twice>(), Some(6))
+
+        let mut buf = None;
+        3u.compute_into::<Twice>(&mut buf);
+        assert_eq!(buf, Some(6))
+        4u.compute_into::<Twice>(&mut buf);
+        assert_eq!(buf, Some(8))
+    }
+
+    struct Digits;
+
+    impl Key for Digits { type Value = Vec<uint>; }
+
+    impl PluginFor<uint> for Digits {
+        fn create(n: &uint) -> Option<Vec<uint>> {
+            let mut buf = None;
+            <Digits as PluginFor<uint>>::create_into(n, &mut buf);
+            buf
+        }
+
+        fn create_into(n: &uint, buf: &mut Option<Vec<uint>>) {
+            if buf.is_none() {
+                *buf = Some(Vec::new());
+            }
+            let digits = buf.as_mut().unwrap();
+            digits.clear();
+            let mut n = *n;
+            while n > 0 {
+                digits.push(n % 10);
+                n /= 10;
+            }
+        }
+    }
+
+    #[test] fn test_compute_into_reuses() {
+        let mut buf = None;
+        1234u.compute_into::<Digits>(&mut buf);
+        assert_eq!(buf, Some(vec![4, 3, 2, 1]))
+        let ptr = buf.as_ref().unwrap().as_ptr();
+        56u.compute_into::<Digits>(&mut buf);
+        assert_eq!(buf, Some(vec![6, 5]))
+        assert_eq!(buf.as_ref().unwrap().as_ptr(), ptr)
+    }
+
+    #[bench] fn bench_get_ref_create(b: &mut Bencher) {
+        b.iter(|| {
+            let mut extended = Extended::new();
+            extended.get_ref::<One>().is_some()
+        })
+    }
+
+    #[bench] fn bench_get_ref_cached(b: &mut Bencher) {
+        let mut extended = Extended::new();
+        extended.get_ref::<One>();
+        b.iter(|| extended.get_ref::<One>().is_some())
+    }
+
+    #[test] fn test_generation() {
+        let mut versioned = Versioned { map: AnyMap::new(), generation: 0 };
+        assert_eq!(versioned.get::<CreatedAt>(), Some(0))
+        versioned.generation = 1;
+        assert_eq!(versioned.get::<CreatedAt>(), Some(1))
+        assert_eq!(versioned.get_ref::<CreatedAt>(), Some(&1))
+        versioned.generation = 2;
+        assert_eq!(versioned.get_mut::<CreatedAt>(), Some(&mut 2))
+        assert_eq!(versioned.take::<CreatedAt>(), Some(2))
+    }
+
+    #[test] fn test_content_hash() {
+        let mut text = Text { text: "one".to_string(), map: AnyMap::new() };
+        assert_eq!(text.get_ref::<Length>(), Some(&3))
+        text.text.push_str(" two");
+        assert_eq!(text.get::<Length>(), Some(7))
+        assert_eq!(text.get_rc::<Length>().map(|len| *len), Some(7))
+        assert_eq!(text.peek::<Length>(), Some(&7))
+        text.text.push_str(" three");
+        assert_eq!(text.peek::<Length>(), None)
+    }
+
+    #[test] fn test_hashes_are_bookkeeping() {
+        let mut text = Text { text: "one".to_string(), map: AnyMap::new() };
+        text.get_ref::<Length>();
+        assert_eq!(text.len(), 1)
+        text.clear();
+        assert!(text.is_empty())
+        assert_eq!(text.get_ref::<Length>(), Some(&3))
+    }
+
+    #[test] fn test_impl_extensible() {
+        let mut derived = Derived { extensions: AnyMap::new() };
+        assert_eq!(derived.get_ref::<One>(), Some(&One(1)))
+        assert!(derived.extensions.contains::<One>())
+    }
+
+    #[test] fn test_get_all() {
+        let mut extended = Extended::new();
+        assert_eq!(get_all!(extended, One, Two, Missing), (Some(One(1)), Some(Two(2)), None))
+        assert!(extended.contains::<One>())
+        assert!(extended.contains::<Two>())
+        assert_eq!(get_all!(extended, Three), (Some(Three(3)),))
+    }
+
+    #[test] fn test_get_rc() {
+        let mut extended = Extended::new();
+        let first = extended.get_rc::<One>().unwrap();
+        let second = extended.get_rc::<One>().unwrap();
+        assert_eq!(*first, One(1))
+        assert_eq!(&*first as *const One, &*second as *const One)
+        assert!(!extended.contains::<One>())
+
+        extended.insert_rc::<One>(Rc::new(One(5)));
+        assert_eq!(extended.get_rc::<One>(), Some(Rc::new(One(5))))
+        assert_eq!(extended.get_rc::<Missing>(), None)
+    }
+
+    #[test] fn test_observer() {
+        let mut extended = Extended::new();
+        let created = Rc::new(RefCell::new(Vec::new()));
+        assert!(extended.set_observer(box Recorder { created: created.clone() }).is_none())
+
+        extended.get_ref::<One>();
+        extended.get_ref::<One>();
+        extended.get::<Two>();
+        extended.get_mut::<Missing>();
+        extended.get_rc::<Three>();
+        assert_eq!(*created.borrow(),
+                   vec![TypeId::of::<One>(), TypeId::of::<Two>(), TypeId::of::<Three>()])
+
+        assert!(extended.remove_observer().is_some())
+        extended.get_ref::<Four>();
+        assert_eq!(created.borrow().len(), 3)
+    }
+
+    #[test] fn test_scope() {
+        let mut extended = Extended::new();
+        extended.get_ref::<One>();
+        extended.get_ref::<Two>();
+        {
+            let mut scope = extended.scope();
+            scope.get_ref::<Three>();
+            scope.insert::<Two>(Two(5));
+            scope.remove::<One>();
+            scope.get_ref::<One>();
+            assert_eq!(scope.len(), 3)
+        }
+        assert!(!extended.contains::<Three>())
+        assert_eq!(extended.get_ref::<One>(), Some(&One(1)))
+        assert_eq!(extended.get_ref::<Two>(), Some(&Two(5)))
+        assert_eq!(extended.len(), 2)
+    }
+
+    #[test] fn test_get_mut_pair() {
+        let mut extended = Extended::new();
+        {
+            let (one, two) = extended.get_mut_pair::<One, Two>();
+            *one.unwrap() = One(5);
+            *two.unwrap() = Two(6);
+        }
+        assert_eq!(extended.get_mut_pair::<One, Two>(), (Some(&mut One(5)), Some(&mut Two(6))))
+
+        extended.remove::<Two>();
+        assert_eq!(extended.get_mut_pair::<Two, Missing>(), (Some(&mut Two(2)), None))
+        assert_eq!(extended.get_mut_pair::<Missing, One>(), (None, Some(&mut One(5))))
+    }
+
+    #[test] fn test_get_mut_pair_unavailable() {
+        let mut versioned = Versioned { map: AnyMap::new(), generation: 1 };
+        versioned.get_ref::<CreatedAt>();
+        versioned.generation = 2;
+        versioned.freeze();
+        assert_eq!(versioned.get_mut_pair::<CreatedAt, NextGeneration>(), (None, None))
+
+        let mut extended = Extended::new();
+        extended.get_ref::<One>();
+        hooks::poison_guard(extended.extensions_mut());
+        assert_eq!(extended.get_mut_pair::<One, Two>(), (None, None))
+    }
+
+    #[test] fn test_get_mut_pair_capacity() {
+        let mut extended = Extended::new();
+        extended.set_capacity(1);
+        assert_eq!(extended.get_mut_pair::<One, Two>(), (Some(&mut One(1)), Some(&mut Two(2))))
+        extended.get_ref::<Three>();
+        assert_eq!(extended.len(), 1)
+    }
+
+    #[test] fn test_get_mut_pair_same() {
+        let mut extended = Extended::new();
+        extended.get_ref::<One>();
+        assert_eq!(extended.get_mut_pair::<One, One>(), (None, None))
+        assert_eq!(extended.get_mut_pair::<Two, Two>(), (None, None))
+        assert!(!extended.contains::<Two>())
+    }
+
+    #[test] fn test_try_with() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.try_with::<Doubled, &'static str, _>(|: _: &mut Doubled| Ok(())),
+                   Err("One is not cached"))
+
+        extended.get_ref::<One>();
+        assert_eq!(extended.try_with::<Doubled, &'static str, _>(|: d: &mut Doubled| {
+            *d = Doubled(5);
+            Err("invalid")
+        }), Err("invalid"))
+        assert!(!extended.contains::<Doubled>())
+
+        assert_eq!(extended.try_with::<Doubled, &'static str, _>(|: d: &mut Doubled| {
+            *d = Doubled(6);
+            Ok(())
+        }), Ok(()))
+        assert_eq!(extended.get_ref_result::<Doubled>(), Ok(&Doubled(6)))
+    }
+
+    #[test] fn test_dependent_cycle() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_ref_dependent::<Ping>(), None)
+        assert_eq!(extended.get_mut_dependent::<Pong>(), None)
+        assert!(extended.is_empty())
+        assert!(extended.extensions().as_raw().is_empty())
+
+        extended.insert::<Pong>(1);
+        assert_eq!(extended.get_ref_dependent::<Ping>(), Some(&2))
+    }
+
+    #[cfg(feature = "no-cache")]
+    #[test] fn test_no_cache() {
+        let mut extended = Extended::new();
+        extended.insert::<One>(One(5));
+        assert_eq!(extended.get::<One>(), Some(One(1)))
+        assert_eq!(extended.get::<Two>(), Some(Two(2)))
+        assert!(!extended.contains::<Two>())
+    }
+
+    #[test] fn test_get_ref_err() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_ref_err::<One>().ok(), Some(&One(1)))
+        match extended.get_ref_err::<Missing>() {
+            Err(PluginError::NotAvailable) => (),
+            other => panic!("expected NotAvailable, got {}", other)
+        }
+    }
+
+    #[test] fn test_modify() {
+        let mut extended = Extended::new();
+        assert!(extended.modify::<One, _>(|: one: &mut One| *one = One(5)))
+        assert!(extended.modify::<One, _>(|: one: &mut One| {
+            let One(n) = *one;
+            *one = One(n + 1);
+        }))
+        assert_eq!(extended.get_ref::<One>(), Some(&One(6)))
+
+        assert!(!extended.modify::<Missing, _>(|: _: &mut Missing| panic!("not created")))
+        assert!(!extended.contains::<Missing>())
+    }
+
+    #[test] fn test_get_with() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_with::<Formatted, _>("unknown"), None)
+        assert_eq!(extended.compute_with::<Formatted, _>("len"),
+                   Some(Formatted("0 plugins".to_string())))
+
+        extended.get_ref::<One>();
+        assert_eq!(extended.get_with::<Formatted, _>("len"),
+                   Some(&Formatted("1 plugins".to_string())))
+        assert_eq!(extended.get_with::<Formatted, _>("unknown"),
+                   Some(&Formatted("1 plugins".to_string())))
+    }
+
+    #[test] fn test_get_ref_named() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.insert_named::<One>("five", One(5)), None)
+        assert_eq!(extended.get_ref_named::<One>("five"), Some(&One(5)))
+        assert_eq!(extended.get_ref_named::<One>("default"), Some(&One(1)))
+        assert_eq!(extended.get_ref_named::<Missing>("default"), None)
+        assert!(!extended.contains::<One>())
+        assert_eq!(extended.len(), 1)
+    }
+
+    #[test] fn test_peek() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.peek::<One>(), None)
+        assert_eq!(extended.peek_mut::<One>(), None)
+        assert!(!extended.contains::<One>())
+
+        extended.get_ref::<One>();
+        *extended.peek_mut::<One>().unwrap() = One(5);
+        assert_eq!(extended.peek::<One>(), Some(&One(5)))
+
+        let mut versioned = Versioned { map: AnyMap::new(), generation: 1 };
+        versioned.get_ref::<CreatedAt>();
+        versioned.generation = 2;
+        assert_eq!(versioned.peek::<CreatedAt>(), None)
+    }
+
+    #[test] fn test_get_ref_if() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_ref_if::<One, _>(|: e: &Extended| !e.is_empty()), None)
+        assert!(!extended.contains::<One>())
+
+        assert_eq!(extended.get_ref_if::<One, _>(|: e: &Extended| e.is_empty()), Some(&One(1)))
+        assert_eq!(extended.get_ref_if::<One, _>(|: _: &Extended| panic!("stored")), Some(&One(1)))
+    }
+
+    #[test] fn test_extend_from() {
+        let mut extended = Extended::new();
+        extended.register_clone::<One>();
+        extended.register_clone::<Three>();
+        extended.get_ref::<One>();
+        extended.get_ref::<Two>();
+        *extended.get_mut::<One>().unwrap() = One(5);
+
+        let mut fork = Extended::new();
+        fork.extend_from(&extended);
+        assert_eq!(fork.peek::<One>(), Some(&One(5)))
+        assert!(!fork.contains::<Two>())
+        assert!(!fork.contains::<Three>())
+        assert_eq!(fork.len(), 1)
+
+        let mut copy = Extended::new();
+        copy.extend_from(&fork);
+        assert_eq!(copy.peek::<One>(), Some(&One(5)))
+    }
+
+    #[test] fn test_get_ref_retry() {
+        let mut extended = Extended::new();
+        extended.insert::<Attempts>(Cell::new(0));
+        assert_eq!(extended.get_ref_retry::<Flaky>(2), None)
+        assert!(!extended.contains::<Flaky>())
+
+        extended.insert::<Attempts>(Cell::new(0));
+        assert_eq!(extended.get_ref_retry::<Flaky>(5), Some(&3))
+        assert_eq!(extended.get_ref_retry::<Flaky>(0), Some(&3))
+        assert_eq!(raw::find::<Attempts>(extended.extensions()).unwrap().get(), 3)
+    }
+
+    #[test] fn test_compute_uncached() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.compute_uncached::<One>(), Some(One(1)))
+        assert!(!extended.contains::<One>())
+
+        extended.insert::<One>(One(5));
+        assert_eq!(extended.compute_uncached::<One>(), Some(One(1)))
+        assert_eq!(extended.get_ref::<One>(), Some(&One(5)))
+    }
+
+    #[test] fn test_tuple() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get::<(One, Two)>(), Some((One(1), Two(2))))
+        assert_eq!(extended.get_ref::<(One, Two, Three)>(), Some(&(One(1), Two(2), Three(3))))
+        assert_eq!(extended.get::<(One, Missing)>(), None)
+        assert!(!extended.contains::<One>())
+        assert_eq!(extended.len(), 2)
+    }
+
+    #[test] fn test_get_ref_dyn() {
+        let mut extended = Extended::new();
+        extended.register_plugin::<One>();
+        extended.register_plugin::<Missing>();
+        assert!(extended.can_create::<One>())
+        assert!(!extended.can_create::<Two>())
+
+        assert_eq!(extended.get_ref_dyn(TypeId::of::<One>()).and_then(|one| one.downcast_ref()),
+                   Some(&One(1)))
+        assert!(extended.get_ref_dyn(TypeId::of::<Missing>()).is_none())
+        assert!(extended.get_ref_dyn(TypeId::of::<Two>()).is_none())
+        assert_eq!(extended.len(), 1)
+
+        extended.clear();
+        assert!(extended.can_create::<One>())
+    }
+
+    #[test] fn test_set_capacity() {
+        let mut extended = Extended::new();
+        extended.set_capacity(2);
+        extended.get_ref::<One>();
+        extended.get::<Two>();
+        extended.get_ref::<One>();
+        extended.get_mut::<Three>();
+        assert!(extended.contains::<One>())
+        assert!(!extended.contains::<Two>())
+        assert!(extended.contains::<Three>())
+        assert_eq!(extended.len(), 2)
+
+        extended.set_capacity(1);
+        assert!(!extended.contains::<One>())
+        assert!(extended.contains::<Three>())
+        assert_eq!(extended.get_ref::<Two>(), Some(&Two(2)))
+        assert_eq!(extended.len(), 1)
+    }
+
+    #[test] fn test_frozen_with_capacity() {
+        let mut extended = Extended::new();
+        extended.set_capacity(1);
+        extended.get_ref::<One>();
+        extended.freeze();
+        assert_eq!(extended.get_ref::<Two>(), None)
+        assert_eq!(extended.get_ref::<One>(), Some(&One(1)))
+        extended.unfreeze();
+        assert_eq!(extended.get_ref::<Two>(), Some(&Two(2)))
+        assert!(!extended.contains::<One>())
+        assert_eq!(extended.len(), 1)
+    }
+
+    #[test] fn test_debug_plugins() {
+        let mut extended = Extended::new();
+        assert!(extended.debug_plugins().ids().is_empty())
+        extended.get_ref::<One>();
+        extended.get_ref::<Missing>();
+        assert_eq!(extended.debug_plugins().ids(), [TypeId::of::<One>()].as_slice())
+        assert!(format!("{}", extended.debug_plugins()).as_slice().contains("One"))
+    }
+
+    #[test] fn test_retain() {
+        let mut extended = Extended::new();
+        extended.set_observer(box Recorder { created: Rc::new(RefCell::new(Vec::new())) });
+        extended.get_ref::<One>();
+        extended.get_ref::<Two>();
+        extended.get_ref::<Three>();
+        let mut calls = 0u;
+        extended.retain(|&mut: id: TypeId| {
+            calls += 1;
+            id != TypeId::of::<Two>()
+        });
+        assert_eq!(calls, 3)
+        assert!(extended.contains::<One>())
+        assert!(!extended.contains::<Two>())
+        assert!(extended.contains::<Three>())
+        assert!(extended.remove_observer().is_some())
+    }
+
+    impl IntoExtensible for Extended {
+        fn into_extensions(self) -> AnyMap { self.map }
+    }
+
+    #[test] fn test_plugin_cache() {
+        let mut memo = Memo { n: 6, extensions: AnyMap::new() };
+        assert_eq!(memo.get_ref::<Halved>(), Some(&3))
+        memo.n = 8;
+        assert_eq!(memo.get_ref::<Halved>(), Some(&3))
+        assert_eq!(memo.compute::<Halved>(), Some(4))
+    }
+
+    mod point {
+        use anymap::AnyMap;
+        use Extensible;
+
+        plugin_cache!(pub struct Point { pub x: uint, y: uint })
+
+        impl Point {
+            pub fn new(x: uint) -> Point {
+                Point { x: x, y: 0, extensions: AnyMap::new() }
+            }
+        }
+    }
+
+    struct Scaled;
+
+    impl Key for Scaled { type Value = uint; }
+
+    impl PluginFor<point::Point> for Scaled {
+        fn create(point: &point::Point) -> Option<uint> { Some(point.x * 2) }
+    }
+
+    #[test] fn test_plugin_cache_pub() {
+        let mut point = point::Point::new(3);
+        assert_eq!(point.get_ref::<Scaled>(), Some(&6))
+        point.x = 4;
+        assert_eq!(point.get_ref::<Scaled>(), Some(&6))
+    }
+
+    #[test] fn test_swap_extensions() {
+        let mut first = Extended::new();
+        let mut second = Extended::new();
+        first.get_ref::<One>();
+        second.insert::<Two>(Two(5));
+        first.swap_extensions(&mut second);
+        assert_eq!(first.peek::<Two>(), Some(&Two(5)))
+        assert!(!first.contains::<One>())
+        assert_eq!(second.peek::<One>(), Some(&One(1)))
+        assert_eq!(second.len(), 1)
+    }
+
+    #[test] fn test_plugin_entry() {
+        let mut extended = Extended::new();
+        match extended.plugin_entry::<Count>() {
+            PluginEntry::Vacant(entry) => *entry.insert(1) += 1,
+            PluginEntry::Occupied(_) => panic!("nothing is stored")
+        }
+        match extended.plugin_entry::<Count>() {
+            PluginEntry::Occupied(mut entry) => {
+                assert_eq!(*entry.get(), 2)
+                *entry.get_mut() += 1;
+                assert_eq!(entry.insert(5), 3)
+                assert_eq!(entry.remove(), 5)
+            },
+            PluginEntry::Vacant(_) => panic!("Count is stored")
+        }
+        assert!(!extended.contains::<Count>())
+    }
+
+    #[test] fn test_get_weak() {
+        let mut extended = Extended::new();
+        let first = extended.get_weak::<One>().unwrap();
+        let second = extended.get_weak::<One>().unwrap();
+        assert_eq!(&*first as *const One, &*second as *const One)
+        assert!(!extended.contains::<One>())
+
+        drop(first);
+        drop(second);
+        let created = Rc::new(RefCell::new(Vec::new()));
+        extended.set_observer(box Recorder { created: created.clone() });
+        assert_eq!(extended.get_weak::<One>(), Some(Rc::new(One(1))))
+        assert_eq!(created.borrow().len(), 1)
+        assert_eq!(extended.get_weak::<Missing>(), None)
+    }
+
+    fn create_two(_: &Extended) -> Option<Box<Any + 'static>> {
+        Some(box Two(5) as Box<Any + 'static>)
+    }
+
+    #[test] fn test_register_dyn() {
+        let mut extended = Extended::new();
+        extended.register_dyn(TypeId::of::<Two>(), create_two);
+        assert!(extended.can_create::<Two>())
+        let two = extended.get_ref_dyn(TypeId::of::<Two>()).and_then(|two| two.downcast_ref());
+        assert_eq!(two, Some(&Two(5)))
+        assert_eq!(extended.get_ref::<Two>(), Some(&Two(5)))
+
+        extended.get_ref::<Three>();
+        assert!(extended.get_ref_dyn(TypeId::of::<Three>()).is_some())
+    }
+
+    #[test] fn test_get_ref_ctx() {
+        let mut extended = Extended::new();
+        extended.insert::<Missing>(Missing(0));
+        assert_eq!(extended.get_ref_ctx::<Parsed>(), None)
+        assert_eq!(raw::find::<Count>(extended.extensions()), Some(&1))
+
+        extended.remove::<Missing>();
+        extended.get_ref::<One>();
+        assert_eq!(extended.get_ref_ctx::<Parsed>(), Some(&Parsed(2)))
+        assert_eq!(raw::find::<Count>(extended.extensions()), Some(&2))
+    }
+
+    #[test] fn test_try_get_ref() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.try_get_ref::<One>(), Ok(&One(1)))
+        assert_eq!(extended.try_get_ref::<Missing>(), Err(GetError::CreateFailed))
+
+        extended.extensions_mut().as_raw_mut().insert(TypeId::of::<Two>(),
+                                                      box "two" as Box<Any + 'static>);
+        assert_eq!(extended.try_get_ref::<Two>(), Err(GetError::TypeMismatch))
+    }
+
+    #[test] fn test_create_mut_take() {
+        let mut request = Request { body: Some("body".to_string()), map: AnyMap::new() };
+        assert_eq!(request.get_ref_dependent::<Body>().map(|s| s.as_slice()), Some("body"))
+        assert!(request.body.is_none())
+        assert_eq!(request.get_mut_dependent::<Body>().map(|s| s.as_slice()), Some("body"))
+
+        request.remove::<Body>();
+        assert_eq!(request.get_ref_dependent::<Body>(), None)
+    }
+
+    #[test] fn test_into_extensions() {
+        let mut extended = Extended::new();
+        extended.get_ref::<One>();
+        let map = extended.into_extensions();
+        assert_eq!(map.find::<One>(), Some(&One(1)))
+        assert_eq!(map.len(), 1)
+    }
+
+    #[test] fn test_get_ref_boxed() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_ref_boxed::<Renderer + 'static>().map(|r| r.render()),
+                   Some("UPPER".to_string()))
+        assert!(extended.contains::<BoxedPlugin<Renderer + 'static>>())
+    }
+
+    #[test] fn test_get_mut_or_default() {
+        let mut extended = Extended::new();
+        *extended.get_mut_or_default::<Count>() += 1;
+        *extended.get_mut_or_default::<Count>() += 1;
+        assert_eq!(raw::find::<Count>(extended.extensions()), Some(&2))
+    }
+
+    #[test] fn test_compute_all() {
+        let extended = Extended::new();
+        assert_eq!(compute_all!(extended, One, Two, Missing), (Some(One(1)), Some(Two(2)), None))
+        assert!(extended.is_empty())
+        assert_eq!(compute_all!(3u, Twice), (Some(6),))
+    }
+
+    #[cfg(feature = "ordered")]
+    #[test] fn test_ordered() {
+        let mut extended = Extended::new();
+        extended.get_ref::<Three>();
+        extended.get_ref::<One>();
+        extended.insert::<Two>(Two(2));
+        extended.remove::<One>();
+        extended.get_ref::<One>();
+        assert_eq!(extended.cached_type_ids().collect::<Vec<TypeId>>(),
+                   vec![TypeId::of::<Three>(), TypeId::of::<One>(), TypeId::of::<Two>()])
+    }
+
+    #[test] fn test_replace_with() {
+        let mut extended = Extended::new();
+        extended.replace_with::<Count, _>(|: old: Option<uint>| { assert_eq!(old, None) None });
+        assert!(!extended.contains::<Count>())
+
+        extended.replace_with::<Count, _>(|: old: Option<uint>| { assert_eq!(old, None) Some(1) });
+        assert_eq!(raw::find::<Count>(extended.extensions()), Some(&1))
+
+        extended.replace_with::<Count, _>(|: old: Option<uint>| old.map(|n| n + 1));
+        assert_eq!(raw::find::<Count>(extended.extensions()), Some(&2))
+
+        extended.replace_with::<Count, _>(|: old: Option<uint>| { assert_eq!(old, Some(2)) None });
+        assert!(!extended.contains::<Count>())
+    }
+
+    #[test] fn test_get_ref_or_alt() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_ref_or_alt::<Missing, One>(), Some(&Missing(2)))
+        assert!(!extended.contains::<One>())
+        assert_eq!(extended.get_ref::<Missing>(), Some(&Missing(2)))
+        assert_eq!(extended.get_ref_or_alt::<Two, Three>(), Some(&Two(2)))
+    }
+
+    struct RemovalRecorder {
+        removed: Rc<RefCell<Vec<TypeId>>>
+    }
+
+    impl PluginObserver for RemovalRecorder {
+        fn created(&self, _: TypeId) {}
+
+        fn removed(&self, id: TypeId) {
+            self.removed.borrow_mut().push(id);
+        }
+    }
+
+    #[test] fn test_observer_removed() {
+        let mut extended = Extended::new();
+        let removed = Rc::new(RefCell::new(Vec::new()));
+        extended.set_observer(box RemovalRecorder { removed: removed.clone() });
+        extended.set_capacity(1);
+        extended.get_ref::<One>();
+        extended.get_ref::<Two>();
+        extended.remove::<Two>();
+        extended.remove::<Two>();
+        extended.get_ref::<Three>();
+        extended.clear();
+        assert_eq!(*removed.borrow(),
+                   vec![TypeId::of::<One>(), TypeId::of::<Two>(), TypeId::of::<Three>()])
+    }
+
+    #[test] fn test_entry_remove_observed() {
+        let mut extended = Extended::new();
+        let removed = Rc::new(RefCell::new(Vec::new()));
+        extended.set_observer(box RemovalRecorder { removed: removed.clone() });
+        extended.get_ref::<One>();
+        match extended.plugin_entry::<One>() {
+            PluginEntry::Occupied(entry) => assert_eq!(entry.remove(), One(1)),
+            PluginEntry::Vacant(_) => panic!("One is stored")
+        }
+        assert_eq!(*removed.borrow(), vec![TypeId::of::<One>()])
+    }
+
+    #[test] fn test_compute_borrowed() {
+        let text = "first second".to_string();
+        assert_eq!(text.compute_borrowed::<FirstWord>(), Some("first"))
+        assert_eq!(String::new().compute_borrowed::<FirstWord>(), None)
+    }
+
+    #[test] fn test_freeze() {
+        let mut extended = Extended::new();
+        extended.get_ref::<One>();
+        extended.freeze();
+        assert!(extended.is_frozen())
+        assert_eq!(extended.get_ref::<One>(), Some(&One(1)))
+        assert_eq!(extended.get_ref::<Two>(), None)
+        assert_eq!(extended.get_mut::<Two>(), None)
+        assert_eq!(extended.get::<Two>(), None)
+        assert!(!extended.contains::<Two>())
+
+        extended.clear();
+        assert!(extended.is_frozen())
+        assert!(extended.is_empty())
+        extended.unfreeze();
+        assert_eq!(extended.get_ref::<Two>(), Some(&Two(2)))
+    }
+
+    #[test] fn test_freeze_every_path() {
+        let mut extended = Extended::new();
+        extended.get_ref::<One>();
+        extended.freeze();
+        assert_eq!(extended.get_ref_retry::<Two>(3), None)
+        assert_eq!(extended.get_ref_by_key(1u, |: key: &uint| Some(*key)), None)
+        assert_eq!(extended.get_ref_or_alt::<Missing, One>(), None)
+        assert_eq!(extended.get_with::<Formatted, _>("len"), None)
+        assert_eq!(extended.get_ref_named::<Two>("second"), None)
+        assert_eq!(extended.get_ref_cached_none::<Two>(), None)
+        assert_eq!(extended.get_rc::<Two>(), None)
+        assert_eq!(extended.get_weak::<Two>(), None)
+        assert_eq!(extended.take::<Two>(), None)
+        assert_eq!(extended.len(), 1)
+        assert_eq!(extended.take::<One>(), Some(One(1)))
+    }
+
+    #[test] fn test_capacity_every_path() {
+        let mut extended = Extended::new();
+        extended.set_capacity(1);
+        extended.get_rc::<One>();
+        extended.get_with::<Formatted, _>("len");
+        assert_eq!(extended.len(), 1)
+        assert!(extended.get_rc::<One>().is_some())
+        assert_eq!(extended.len(), 1)
+        extended.get_ref_named::<Two>("second");
+        extended.get_ref_by_key(1u, |: key: &uint| Some(*key));
+        assert_eq!(extended.len(), 1)
+        assert!(extended.get_ref_dependent::<Sum>().is_some())
+        assert_eq!(extended.len(), 1)
+    }
+
+    struct Settings {
+        name: String,
+        retries: uint
+    }
+
+    impl Key for Settings { type Value = Settings; }
+
+    impl PluginFor<Extended> for Settings {
+        fn create(_: &Extended) -> Option<Settings> {
+            Some(Settings { name: "default".to_string(), retries: 3 })
+        }
+    }
+
+    #[test] fn test_map_ref() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.map_ref::<Settings, uint, _>(|settings| &settings.retries), Some(&3))
+        assert_eq!(extended.map_ref::<Settings, str, _>(|settings| settings.name.as_slice()),
+                   Some("default"))
+        assert_eq!(extended.map_ref::<Missing, Missing, _>(|missing| missing), None)
+    }
+
+    #[test] fn test_get_ref_by_key() {
+        let mut extended = Extended::new();
+        let calls = Cell::new(0u);
+        for &(pattern, len) in [("ab", Some(2u)), ("abc", Some(3)), ("ab", Some(2)),
+                                ("", None), ("", None)].iter() {
+            let found = extended.get_ref_by_key(pattern, |: pattern: &&'static str| {
+                calls.set(calls.get() + 1);
+                if pattern.is_empty() { None } else { Some(pattern.len()) }
+            });
+            assert_eq!(found, len.as_ref())
+        }
+        assert_eq!(calls.get(), 4)
+        assert_eq!(extended.len(), 1)
+
+        let mut failed = Extended::new();
+        assert_eq!(failed.get_ref_by_key(0u, |: _: &uint| None::<uint>), None)
+        assert!(failed.is_empty())
+    }
+
+    #[test] fn test_ensure_all() {
+        let mut extended = Extended::new();
+        assert!(ensure_all!(extended, One, Two))
+        assert!(!ensure_all!(extended, Missing, Three))
+        assert!(extended.contains::<Three>())
+        assert!(!extended.contains::<Missing>())
+        assert_eq!(extended.len(), 3)
+    }
+
+    #[test] fn test_shrink_to_fit() {
+        let mut extended = Extended::new();
+        extended.reserve(10);
+        extended.get::<One>();
+        extended.get::<Two>();
+        extended.remove::<Two>();
+        extended.shrink_to_fit();
+        assert_eq!(extended.len(), 1)
+        assert_eq!(extended.get_ref::<One>(), Some(&One(1)))
+    }
+
+    #[test] fn test_compute_deref() {
+        let n = Rc::new(3u);
+        assert_eq!(n.compute_deref::<Twice, uint>(), Some(6))
+
+        let extended = box Extended::new();
+        assert_eq!(extended.compute_deref::<One, Extended>(), Some(One(1)))
+        assert!(!extended.contains::<One>())
+    }
+
+    #[deriving(PartialEq, Show)]
+    struct Failure(&'static str);
+
+    impl FromError<&'static str> for Failure {
+        fn from_error(reason: &'static str) -> Failure { Failure(reason) }
+    }
+
+    impl FallibleGetCached for Extended { type Error = Failure; }
+
+    #[test] fn test_fallible_get_cached() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_fallible::<Doubled>(), Err(Failure("One is not cached")))
+        extended.get_ref::<One>();
+        assert_eq!(extended.get_mut_fallible::<Doubled>(), Ok(&mut Doubled(2)))
+        assert_eq!(extended.get_ref_fallible::<Doubled>(), Ok(&Doubled(2)))
+        assert_eq!(extended.peek::<Doubled>(), Some(&Doubled(2)))
+    }
+
+    #[test] fn test_get_ref_status() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_ref_status::<One>(), (Some(&One(1)), true))
+        assert_eq!(extended.get_ref_status::<One>(), (Some(&One(1)), false))
+        assert_eq!(extended.get_ref_status::<Missing>(), (None, true))
+        extended.freeze();
+        assert_eq!(extended.get_ref_status::<Two>(), (None, false))
+
+        let mut poisoned = Extended::new();
+        hooks::poison_guard(poisoned.extensions_mut());
+        assert_eq!(poisoned.get_ref_status::<One>(), (None, false))
+    }
+
+    #[deriving(Copy, Clone, PartialEq, Show)]
+    struct Flag(bool);
+
+    impl Key for Flag { type Value = Flag; }
+
+    impl PluginFor<Extended> for Flag {
+        fn create(extended: &Extended) -> Option<Flag> { Some(Flag(extended.is_empty())) }
+    }
+
+    #[test] fn test_get_copy() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_copy::<Flag>(), Some(Flag(true)))
+        assert_eq!(extended.get_copy::<Flag>(), Some(Flag(true)))
+        assert_eq!(extended.get_copy::<Missing>(), None)
+    }
+
+    #[deriving(Default, PartialEq, Show)]
+    struct Limits {
+        max: uint
+    }
+
+    impl Key for Limits { type Value = Limits; }
+
+    struct Names;
+
+    impl Key for Names { type Value = Vec<String>; }
+
+    default_plugin!(Extended, Limits, Names)
+
+    #[test] fn test_default_plugin() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_ref::<Limits>(), Some(&Limits { max: 0 }))
+        assert_eq!(extended.get_ref::<Names>(), Some(&Vec::new()))
+    }
+
+    struct Template;
+
+    impl Key for Template { type Value = uint; }
+
+    impl PluginFor<uint> for Template {
+        fn create(n: &uint) -> Option<uint> { Some(*n) }
+    }
+
+    #[test] fn test_compute_thread_cached() {
+        assert_eq!(1u.compute_thread_cached::<Template>().map(|t| *t), Some(1))
+        assert_eq!(2u.compute_thread_cached::<Template>().map(|t| *t), Some(1))
+        clear_thread_cache();
+        assert_eq!(2u.compute_thread_cached::<Template>().map(|t| *t), Some(2))
+        assert_eq!(Extended::new().compute_thread_cached::<Missing>(), None)
+    }
+
+    #[test] fn test_get_ref_with_deps() {
+        let mut extended = Extended::new();
+        let preps = Cell::new(0u);
+        for _ in range(0u, 2) {
+            let tripled = extended.get_ref_with_deps::<Tripled, _>(|: extended: &mut Extended| {
+                preps.set(preps.get() + 1);
+                extended.get_ref::<One>();
+            });
+            assert_eq!(tripled, Some(&Tripled(3)))
+        }
+        assert_eq!(preps.get(), 2)
+    }
+
+    #[deriving(PartialEq)]
+    enum Phase {
+        Request,
+        Response
+    }
+
+    #[test] fn test_count_in_category() {
+        let mut extended = Extended::new();
+        extended.register_plugin_category::<One, _>(Phase::Request);
+        extended.register_plugin_category::<Two, _>(Phase::Request);
+        extended.register_plugin_category::<Three, _>(Phase::Response);
+        extended.register_plugin_category::<Four, _>("request");
+        extended.get_ref::<One>();
+        extended.get_ref::<Two>();
+        extended.get_ref::<Four>();
+        extended.get_ref::<Five>();
+        assert_eq!(extended.count_in_category(Phase::Request), 2)
+        assert_eq!(extended.count_in_category(Phase::Response), 0)
+        assert_eq!(extended.count_in_category("request"), 1)
+        extended.clear();
+        extended.get_ref::<Three>();
+        assert_eq!(extended.count_in_category(Phase::Response), 1)
+        assert_eq!(extended.len(), 1)
+    }
+
+    #[test] fn test_poison() {
+        let mut extended = Extended::new();
+        extended.get_ref::<One>();
+        assert_eq!(extended.get_ref_dependent::<Sum>(), Some(&Sum(3)))
+        assert!(!extended.is_poisoned())
+
+        // Dropped armed, as when create_mut panics.
+        hooks::poison_guard(extended.extensions_mut());
+        assert!(extended.is_poisoned())
+        assert_eq!(extended.get_ref::<One>(), None)
+        assert_eq!(extended.get::<Two>(), None)
+        assert_eq!(extended.get_ref_dependent::<Sum>(), None)
+        assert_eq!(extended.try_get_ref::<One>(), Err(GetError::Poisoned))
+
+        extended.clear();
+        assert!(extended.is_poisoned())
+        extended.clear_poison();
+        assert_eq!(extended.get_ref::<One>(), Some(&One(1)))
+    }
+
+    struct Exploding;
+
+    impl Key for Exploding { type Value = uint; }
+
+    impl PluginForMut<Extended> for Exploding {
+        fn create_mut(extended: &mut Extended) -> Option<uint> {
+            extended.get_ref::<Two>();
+            panic!("create_mut of Exploding")
+        }
+    }
+
+    #[test] fn test_poison_on_panic() {
+        let mut extended = Extended::new();
+        extended.get_ref::<One>();
+        {
+            // task::try needs a Send closure, which can't borrow extended, so
+            // catch the panic on this task.
+            let extended = &mut extended;
+            let result = unsafe {
+                unwind::try(move |:| { extended.get_ref_dependent::<Exploding>(); })
+            };
+            assert!(result.is_err())
+        }
+        assert!(extended.is_poisoned())
+        assert!(extended.contains::<Two>())
+        assert_eq!(extended.get_ref::<One>(), None)
+        assert_eq!(extended.get_ref_dependent::<Sum>(), None)
+        assert_eq!(extended.get_rc::<Three>(), None)
+
+        extended.clear_poison();
+        assert!(!extended.is_poisoned())
+        assert_eq!(extended.get_ref::<One>(), Some(&One(1)))
+        assert_eq!(extended.get_ref_dependent::<Sum>(), Some(&Sum(3)))
+    }
+
+    struct Buffered;
+
+    impl Key for Buffered { type Value = Vec<u8>; }
+
+    impl PluginFor<Extended> for Buffered {
+        fn create(_: &Extended) -> Option<Vec<u8>> { Some(Vec::with_capacity(64)) }
+    }
+
+    impl SizeHint for Vec<u8> {
+        fn heap_size(&self) -> uint { self.capacity() }
+    }
+
+    #[test] fn test_plugin_memory_hint() {
+        let mut extended = Extended::new();
+        extended.register_size_hint::<Buffered>();
+        assert_eq!(extended.plugin_memory_hint(), 0)
+        extended.get_ref::<One>();
+        let capacity = extended.get_ref::<Buffered>().unwrap().capacity();
+        assert_eq!(extended.plugin_memory_hint(), mem::size_of::<Vec<u8>>() + capacity)
+        extended.clear();
+        assert_eq!(extended.plugin_memory_hint(), 0)
+    }
+
+    #[test] fn test_compute_cached_in() {
+        let mut cache = AnyMap::new();
+        {
+            let first = 3u;
+            assert_eq!(first.compute_cached_in::<Twice>(&mut cache), Some(&6))
+        }
+        assert_eq!(4u.compute_cached_in::<Twice>(&mut cache), Some(&6))
+        assert_eq!(Extended::new().compute_cached_in::<Missing>(&mut cache), None)
+        assert!(!cache.contains::<Missing>())
+    }
+
+    #[test] fn test_get_cow() {
+        let mut extended = Extended::new();
+        match extended.get_cow::<One>() {
+            Some(PluginCow::Borrowed(one)) => assert_eq!(*one, One(1)),
+            Some(PluginCow::Owned(one)) => assert_eq!(one, One(1)),
+            None => panic!("One is always created")
+        }
+        assert_eq!(extended.get_cow::<Two>().map(|two| two.into_owned()), Some(Two(2)))
+        assert!(extended.get_cow::<Missing>().is_none())
+    }
+
+    #[test] fn test_versioned_bookkeeping() {
+        let mut versioned = Versioned { map: AnyMap::new(), generation: 1 };
+        versioned.get_ref::<CreatedAt>();
+        versioned.get_ref::<NextGeneration>();
+        assert_eq!(versioned.len(), 2)
+        assert_eq!(versioned.cached_type_ids().count(), 2)
+
+        versioned.retain(|&mut: id: TypeId| id == TypeId::of::<CreatedAt>());
+        assert_eq!(versioned.len(), 1)
+        assert!(versioned::is_fresh::<CreatedAt, Versioned>(&versioned))
+        assert!(!versioned.is_empty())
+    }
+}
@@ -0,0 +1,95 @@
+//! Eviction of the least recently accessed plugins once an extensible type
+//! stores more than its capacity.
+
+use std::intrinsics::TypeId;
+use std::mem;
+use anymap::AnyMap;
+use {Extensible, Key};
+use hooks;
+use raw;
+
+/// The key under which the capacity and access order of an extensible type
+/// are stored.
+pub struct LruKey;
+
+impl Key for LruKey { type Value = Lru; }
+
+bookkeeping!(LruKey)
+
+/// The capacity of an extensible type, and its tracked plugins from least to
+/// most recently accessed.
+pub struct Lru {
+    /// The number of tracked plugins kept.
+    pub capacity: uint,
+    /// The TypeIds of the keys of tracked plugins.
+    pub order: Vec<TypeId>,
+    /// The TypeId of the key of a plugin which is not evicted however old,
+    /// while get_mut_pair creates the other of its pair.
+    pub pinned: Option<TypeId>
+}
+
+/// Records that K was accessed, evicting the least recently accessed plugins
+/// while more than the capacity of extensible are tracked. Does nothing if
+/// no capacity is set, though callers on the hot path check
+/// hooks::Flags::bounded first to skip the lookup.
+pub fn touch<K: Key, E: Extensible>(extensible: &mut E) {
+    let map = extensible.extensions_mut();
+    let mut lru = match raw::remove::<LruKey>(map) {
+        Some(lru) => lru,
+        None => return
+    };
+    let id = TypeId::of::<K>();
+    lru.order.retain(|other| *other != id && map.as_raw().contains_key(other));
+    if map.as_raw().contains_key(&id) {
+        lru.order.push(id);
+    }
+    evict(map, lru);
+}
+
+/// Sets the capacity of map to n, evicting the least recently accessed
+/// plugins if more are tracked.
+pub fn set_capacity(map: &mut AnyMap, n: uint) {
+    let mut lru = raw::remove::<LruKey>(map)
+        .unwrap_or(Lru { capacity: n, order: Vec::new(), pinned: None });
+    lru.capacity = n;
+    evict(map, lru);
+    hooks::set_bounded(map);
+}
+
+/// Keeps the plugin whose key has the TypeId id from being evicted until
+/// another is pinned, or unpins it if id is None. Does nothing if no
+/// capacity is set.
+pub fn pin(map: &mut AnyMap, id: Option<TypeId>) {
+    match raw::find_mut::<LruKey>(map) {
+        Some(lru) => lru.pinned = id,
+        None => ()
+    }
+}
+
+/// Removes the least recently accessed plugins other than the pinned one
+/// while more than the capacity are tracked, not counting the pinned one,
+/// then stores lru in map.
+fn evict(map: &mut AnyMap, mut lru: Lru) {
+    let pinned = lru.pinned;
+    let spared = match pinned {
+        Some(ref id) if lru.order.contains(id) => 1,
+        _ => 0
+    };
+    if lru.order.len() > lru.capacity + spared {
+        let mut excess = lru.order.len() - lru.capacity - spared;
+        let (evicted, kept) = mem::replace(&mut lru.order, Vec::new()).partition(|id| {
+            let evict = excess > 0 && Some(*id) != pinned;
+            if evict {
+                excess -= 1;
+            }
+            evict
+        });
+        for old in evicted.iter() {
+            if map.as_raw_mut().remove(old).is_some() {
+                hooks::removed(map, *old);
+            }
+        }
+        lru.order = kept;
+    }
+    raw::insert::<LruKey>(map, lru);
+}
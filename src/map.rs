@@ -0,0 +1,124 @@
+//! A ready-made extensible type.
+
+use anymap::AnyMap;
+use {Extensible, IntoExtensible, Key};
+use raw;
+
+/// An extensible type which is only its extension storage, for plugins
+/// which don't need a source type of their own.
+///
+/// It derefs to the underlying AnyMap.
+pub struct ExtensibleMap {
+    map: AnyMap
+}
+
+impl ExtensibleMap {
+    /// Create an ExtensibleMap with no plugins stored.
+    pub fn new() -> ExtensibleMap {
+        ExtensibleMap { map: AnyMap::new() }
+    }
+
+    /// Create an ExtensibleMap storing the plugins already in map.
+    pub fn from_anymap(map: AnyMap) -> ExtensibleMap {
+        ExtensibleMap { map: map }
+    }
+}
+
+/// A builder of extension storage with values stored under some keys from
+/// the start, so that get_ref and the other methods return them without
+/// calling create.
+///
+/// ```ignore
+/// let map = ExtensibleBuilder::new().with::<Url>(url).with::<Headers>(headers).build();
+/// ```
+pub struct ExtensibleBuilder {
+    map: AnyMap
+}
+
+impl ExtensibleBuilder {
+    /// Create an ExtensibleBuilder with no values stored.
+    pub fn new() -> ExtensibleBuilder {
+        ExtensibleBuilder { map: AnyMap::new() }
+    }
+
+    /// Store value under K, in place of any value stored under K before.
+    pub fn with<K: Key>(mut self, value: K::Value) -> ExtensibleBuilder {
+        raw::insert::<K>(&mut self.map, value);
+        self
+    }
+
+    /// Build an ExtensibleMap with the stored values.
+    pub fn build(self) -> ExtensibleMap {
+        ExtensibleMap::from_anymap(self.map)
+    }
+
+    /// Build extension storage with the stored values, for extensible types
+    /// other than ExtensibleMap.
+    pub fn build_anymap(self) -> AnyMap {
+        self.map
+    }
+}
+
+impl Extensible for ExtensibleMap {
+    fn extensions(&self) -> &AnyMap { &self.map }
+    fn extensions_mut(&mut self) -> &mut AnyMap { &mut self.map }
+}
+
+impl IntoExtensible for ExtensibleMap {
+    fn into_extensions(self) -> AnyMap { self.map }
+}
+
+impl Deref<AnyMap> for ExtensibleMap {
+    fn deref<'a>(&'a self) -> &'a AnyMap { &self.map }
+}
+
+impl DerefMut<AnyMap> for ExtensibleMap {
+    fn deref_mut<'a>(&'a mut self) -> &'a mut AnyMap { &mut self.map }
+}
+
+#[cfg(test)]
+mod test {
+    use anymap::AnyMap;
+    use {GetCached, IntoExtensible, Key, PluginFor};
+    use super::{ExtensibleBuilder, ExtensibleMap};
+
+    #[deriving(PartialEq, Show, Clone)]
+    struct One(uint);
+
+    impl Key for One { type Value = One; }
+
+    impl PluginFor<ExtensibleMap> for One {
+        fn create(_: &ExtensibleMap) -> Option<One> { Some(One(1)) }
+    }
+
+    #[test] fn test_new() {
+        let mut map = ExtensibleMap::new();
+        assert_eq!(map.get_ref::<One>(), Some(&One(1)))
+        assert_eq!(map.find::<One>(), Some(&One(1)))
+    }
+
+    #[test] fn test_from_anymap() {
+        let mut anymap = AnyMap::new();
+        anymap.insert(One(5));
+        let mut map = ExtensibleMap::from_anymap(anymap);
+        assert_eq!(map.get_ref::<One>(), Some(&One(5)))
+    }
+
+    #[test] fn test_into_extensions() {
+        let mut map = ExtensibleMap::new();
+        map.get_ref::<One>();
+        assert_eq!(map.into_extensions().find::<One>(), Some(&One(1)))
+    }
+
+    #[deriving(PartialEq, Show, Clone)]
+    struct Two(uint);
+
+    impl Key for Two { type Value = Two; }
+
+    #[test] fn test_builder() {
+        let mut map = ExtensibleBuilder::new().with::<One>(One(5)).with::<Two>(Two(2)).build();
+        assert_eq!(map.get_ref::<One>(), Some(&One(5)))
+        assert_eq!(map.len(), 2)
+        assert!(ExtensibleBuilder::new().build_anymap().is_empty())
+    }
+}
@@ -0,0 +1,73 @@
+//! Plugins whose values are derived from the values of other plugins.
+
+use {Key, PluginFor};
+
+/// A conversion of the value of a plugin into the value of a MapPlugin.
+///
+/// map takes no self, as create has none to pass on, so the mapper is a
+/// type, usually zero-sized, rather than a closure.
+pub trait Mapper<T>: 'static {
+    /// The type of the converted value.
+    type Output: 'static;
+
+    /// Convert the value of a plugin.
+    fn map(value: T) -> Self::Output;
+}
+
+/// A plugin whose value is created by creating Src's value and converting it
+/// through M, so a derived plugin needs no create of its own.
+///
+/// Src's value is created afresh for each creation of the MapPlugin, not
+/// taken from the cache; only the converted value is stored.
+///
+/// ```ignore
+/// struct Length;
+///
+/// impl Mapper<String> for Length {
+///     type Output = uint;
+///     fn map(body: String) -> uint { body.len() }
+/// }
+///
+/// let length = request.get::<MapPlugin<Body, Length>>();
+/// ```
+pub struct MapPlugin<Src, M>;
+
+impl<Src: Key, M: Mapper<Src::Value>> Key for MapPlugin<Src, M> {
+    type Value = <M as Mapper<Src::Value>>::Output;
+}
+
+impl<T, Src, M> PluginFor<T> for MapPlugin<Src, M>
+where Src: PluginFor<T>, M: Mapper<Src::Value> {
+    fn create(source: &T) -> Option<<M as Mapper<Src::Value>>::Output> {
+        <Src as PluginFor<T>>::create(source).map(|value| <M as Mapper<Src::Value>>::map(value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {Get, Key, PluginFor};
+    use super::{MapPlugin, Mapper};
+
+    struct Text;
+
+    impl Key for Text { type Value = String; }
+
+    impl PluginFor<uint> for Text {
+        fn create(n: &uint) -> Option<String> {
+            if *n == 0 { None } else { Some(n.to_string()) }
+        }
+    }
+
+    struct Length;
+
+    impl Mapper<String> for Length {
+        type Output = uint;
+
+        fn map(text: String) -> uint { text.len() }
+    }
+
+    #[test] fn test_map_plugin() {
+        assert_eq!(120u.compute::<MapPlugin<Text, Length>>(), Some(3))
+        assert_eq!(0u.compute::<MapPlugin<Text, Length>>(), None)
+    }
+}
@@ -0,0 +1,32 @@
+//! The type names of the keys of stored plugins, for debug_plugins.
+//!
+//! The values are type-erased once stored, so the name of each key is
+//! recorded when a value is first stored under it through its type.
+
+use std::collections::HashMap;
+use std::intrinsics::TypeId;
+use anymap::AnyMap;
+use Key;
+use raw;
+
+/// The key under which the type names of keys are stored, by their TypeIds.
+struct NamesKey;
+
+impl Key for NamesKey { type Value = HashMap<TypeId, &'static str>; }
+
+bookkeeping!(NamesKey)
+
+/// Records the type name of K in map.
+pub fn record<K: Key>(map: &mut AnyMap) {
+    let id = TypeId::of::<K>();
+    let names = raw::get_or_insert_with::<NamesKey>(map, HashMap::new);
+    if !names.contains_key(&id) {
+        names.insert(id, raw::type_name::<K>());
+    }
+}
+
+/// Returns the type name of the key with TypeId id, if one was recorded in
+/// map.
+pub fn find(map: &AnyMap, id: &TypeId) -> Option<&'static str> {
+    raw::find::<NamesKey>(map).and_then(|names| names.get(id)).map(|name| *name)
+}
@@ -0,0 +1,42 @@
+//! The order in which plugins were first stored, enabled by the `ordered`
+//! cargo feature, so that they are iterated in a stable order.
+//!
+//! Storing a plugin for the first time then takes time linear in the number
+//! of plugins ever stored, and iterating them allocates.
+
+use std::intrinsics::TypeId;
+use anymap::AnyMap;
+use Key;
+use raw;
+
+/// The key under which the TypeIds of the keys of stored plugins are
+/// stored, in the order they were first stored.
+pub struct OrderKey;
+
+impl Key for OrderKey { type Value = Vec<TypeId>; }
+
+bookkeeping!(OrderKey)
+
+/// Records that a value is being stored under the key with TypeId id.
+pub fn record(map: &mut AnyMap, id: TypeId) {
+    let order = raw::get_or_insert_with::<OrderKey>(map, Vec::new);
+    if !order.contains(&id) {
+        order.push(id);
+    }
+}
+
+/// Returns the TypeIds of the keys of every entry in map: first those
+/// recorded, in the order they were first stored, then the rest in order of
+/// hash.
+pub fn ids(map: &AnyMap) -> Vec<TypeId> {
+    let mut ids: Vec<TypeId> = match raw::find::<OrderKey>(map) {
+        Some(order) => order.iter().filter(|id| map.as_raw().contains_key(*id))
+                                   .map(|id| *id).collect(),
+        None => Vec::new()
+    };
+    let mut rest: Vec<TypeId> = map.as_raw().keys().filter(|id| !ids.contains(*id))
+                                                   .map(|id| *id).collect();
+    rest.sort_by(|a, b| a.hash().cmp(&b.hash()));
+    ids.extend(rest.into_iter());
+    ids
+}
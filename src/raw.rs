@@ -0,0 +1,209 @@
+//! Storage of plugin values in an AnyMap under the TypeId of their key.
+//!
+//! A key whose value is itself is stored exactly where AnyMap would store it.
+//!
+//! Every value is downcast through value_ref, value_mut or value; in debug
+//! builds they panic if the stored value is not of its key's value type.
+
+use std::any::{Any, AnyRefExt, AnyMutRefExt, BoxAny};
+use std::intrinsics::{TypeId, get_tydesc};
+#[cfg(not(feature = "ordered"))]
+use std::collections::hash_map::Keys;
+#[cfg(feature = "ordered")]
+use std::kinds::marker::ContravariantLifetime;
+#[cfg(feature = "ordered")]
+use std::vec::MoveItems;
+use anymap::AnyMap;
+use Key;
+use hooks;
+use storage::Storage;
+#[cfg(feature = "ordered")]
+use order;
+
+/// Returns true if a value is stored under K.
+pub fn contains<K: Key>(map: &AnyMap) -> bool {
+    Storage::contains::<K>(map)
+}
+
+/// Returns a reference to the value stored under K, if any.
+pub fn find<K: Key>(map: &AnyMap) -> Option<&K::Value> {
+    Storage::find::<K>(map)
+}
+
+/// Returns a mutable reference to the value stored under K, if any.
+pub fn find_mut<K: Key>(map: &mut AnyMap) -> Option<&mut K::Value> {
+    Storage::find_mut::<K>(map)
+}
+
+/// Stores value under K, returning the value previously stored under K.
+pub fn insert<K: Key>(map: &mut AnyMap, value: K::Value) -> Option<K::Value> {
+    Storage::insert::<K>(map, value)
+}
+
+/// Returns a mutable reference to the value stored under K, first storing
+/// init's value under K if none is.
+pub fn get_or_insert_with<K: Key>(map: &mut AnyMap, init: fn() -> K::Value) -> &mut K::Value {
+    if !contains::<K>(map) {
+        insert::<K>(map, init());
+    }
+    find_mut::<K>(map).unwrap()
+}
+
+/// Removes and returns the value stored under K, if any.
+pub fn remove<K: Key>(map: &mut AnyMap) -> Option<K::Value> {
+    Storage::remove::<K>(map)
+}
+
+/// Returns true if no value is stored under K, or one of K's value type is.
+/// Unlike the other functions here, this never panics.
+pub fn matches<K: Key>(map: &AnyMap) -> bool {
+    match map.as_raw().get(&TypeId::of::<K>()) {
+        Some(value) => (**value).get_type_id() == TypeId::of::<K::Value>(),
+        None => true
+    }
+}
+
+/// Downcasts a value stored under K.
+pub fn value_ref<K: Key>(value: &Box<Any + 'static>) -> Option<&K::Value> {
+    check::<K>(&**value);
+    value.downcast_ref()
+}
+
+/// Downcasts a value stored under K mutably.
+pub fn value_mut<K: Key>(value: &mut Box<Any + 'static>) -> Option<&mut K::Value> {
+    check::<K>(&**value);
+    value.downcast_mut()
+}
+
+/// Downcasts and unboxes a value stored under K.
+pub fn value<K: Key>(value: Box<Any + 'static>) -> Option<K::Value> {
+    check::<K>(&*value);
+    value.downcast().ok().map(|value| *value)
+}
+
+/// Panics in debug builds if value is not of K's value type, which means it
+/// was stored under K by something other than this crate.
+fn check<K: Key>(value: &Any) {
+    debug_assert!(value.get_type_id() == TypeId::of::<K::Value>(),
+                  "the value stored under {} is not a {}",
+                  type_name::<K>(), type_name::<K::Value>());
+}
+
+/// Returns the name of T.
+pub fn type_name<T>() -> &'static str {
+    unsafe { (*get_tydesc::<T>()).name }
+}
+
+/// Removes every value whose key's TypeId fails keep, telling the observer.
+pub fn retain<F: FnMut(&TypeId) -> bool>(map: &mut AnyMap, mut keep: F) {
+    let removed: Vec<TypeId> = map.as_raw().keys().filter(|id| !keep(*id)).map(|id| *id).collect();
+    for id in removed.iter() {
+        map.as_raw_mut().remove(id);
+        hooks::removed(map, *id);
+    }
+}
+
+/// Returns an iterator over the TypeIds of the keys of every stored plugin,
+/// skipping the crate's own bookkeeping entries.
+#[cfg(not(feature = "ordered"))]
+pub fn type_ids(map: &AnyMap) -> TypeIds {
+    TypeIds { keys: map.as_raw().keys() }
+}
+
+/// Returns an iterator over the TypeIds of the keys of every stored plugin,
+/// skipping the crate's own bookkeeping entries.
+#[cfg(feature = "ordered")]
+pub fn type_ids(map: &AnyMap) -> TypeIds {
+    TypeIds { ids: order::ids(map).into_iter(), marker: ContravariantLifetime }
+}
+
+/// An iterator over the TypeIds of the keys of stored plugins, in no
+/// particular order.
+#[cfg(not(feature = "ordered"))]
+pub struct TypeIds<'a> {
+    keys: Keys<'a, TypeId, Box<Any + 'static>>
+}
+
+/// An iterator over the TypeIds of the keys of stored plugins, in the order
+/// they were first stored.
+#[cfg(feature = "ordered")]
+pub struct TypeIds<'a> {
+    ids: MoveItems<TypeId>,
+    marker: ContravariantLifetime<'a>
+}
+
+#[cfg(not(feature = "ordered"))]
+impl<'a> Iterator<TypeId> for TypeIds<'a> {
+    fn next(&mut self) -> Option<TypeId> {
+        loop {
+            match self.keys.next() {
+                Some(id) if hooks::is_bookkeeping(id) => continue,
+                other => return other.map(|id| *id)
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        (0, self.keys.size_hint().val1())
+    }
+}
+
+#[cfg(feature = "ordered")]
+impl<'a> Iterator<TypeId> for TypeIds<'a> {
+    fn next(&mut self) -> Option<TypeId> {
+        loop {
+            match self.ids.next() {
+                Some(id) if hooks::is_bookkeeping(&id) => continue,
+                other => return other
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        (0, self.ids.size_hint().val1())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::any::Any;
+    use std::intrinsics::TypeId;
+    use anymap::AnyMap;
+    use Key;
+
+    struct Count;
+
+    impl Key for Count { type Value = uint; }
+
+    #[test] fn test_find() {
+        let mut map = AnyMap::new();
+        assert_eq!(super::insert::<Count>(&mut map, 1), None)
+        assert_eq!(super::insert::<Count>(&mut map, 2), Some(1))
+        assert_eq!(super::find::<Count>(&map), Some(&2))
+        assert_eq!(super::remove::<Count>(&mut map), Some(2))
+        assert!(!super::contains::<Count>(&map))
+    }
+
+    #[test] fn test_get_or_insert_with() {
+        let mut map = AnyMap::new();
+        *super::get_or_insert_with::<Count>(&mut map, Default::default) += 2;
+        *super::get_or_insert_with::<Count>(&mut map, Default::default) += 1;
+        assert_eq!(super::find::<Count>(&map), Some(&3))
+    }
+
+    #[test] fn test_matches() {
+        let mut map = AnyMap::new();
+        assert!(super::matches::<Count>(&map))
+        super::insert::<Count>(&mut map, 1);
+        assert!(super::matches::<Count>(&map))
+        map.as_raw_mut().insert(TypeId::of::<Count>(), box "one" as Box<Any + 'static>);
+        assert!(!super::matches::<Count>(&map))
+    }
+
+    #[cfg(not(ndebug))]
+    #[test] #[should_fail] fn test_mismatch() {
+        let mut map = AnyMap::new();
+        map.as_raw_mut().insert(TypeId::of::<Count>(), box "one" as Box<Any + 'static>);
+        super::find::<Count>(&map);
+    }
+}
@@ -0,0 +1,42 @@
+//! Scopes which remove the plugins stored during them.
+
+use std::collections::HashSet;
+use std::intrinsics::TypeId;
+use {Extensible, GetCached};
+use hooks;
+use raw;
+
+/// A scope within which plugins are stored in an extensible type as usual,
+/// but which removes every plugin stored during it when it is dropped.
+///
+/// The scope derefs to the extensible type. Plugins stored before the scope
+/// began are kept, even if they were removed and stored again, or replaced,
+/// during it; they keep their latest value.
+pub struct PluginScope<'a, E: 'a> {
+    extensible: &'a mut E,
+    before: HashSet<TypeId>
+}
+
+/// Begins a scope on extensible.
+pub fn begin<'a, E: GetCached>(extensible: &'a mut E) -> PluginScope<'a, E> {
+    let before = extensible.cached_type_ids().collect();
+    PluginScope { extensible: extensible, before: before }
+}
+
+impl<'a, E> Deref<E> for PluginScope<'a, E> {
+    fn deref<'b>(&'b self) -> &'b E { &*self.extensible }
+}
+
+impl<'a, E> DerefMut<E> for PluginScope<'a, E> {
+    fn deref_mut<'b>(&'b mut self) -> &'b mut E { &mut *self.extensible }
+}
+
+#[unsafe_destructor]
+impl<'a, E: Extensible> Drop for PluginScope<'a, E> {
+    fn drop(&mut self) {
+        let before = &self.before;
+        raw::retain(self.extensible.extensions_mut(), |&mut: id: &TypeId| {
+            before.contains(id) || hooks::is_bookkeeping(id)
+        });
+    }
+}
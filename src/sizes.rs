@@ -0,0 +1,54 @@
+//! Estimates of the memory used by stored plugins.
+//!
+//! Values are type-erased in an AnyMap, so each plugin whose size is counted
+//! must be registered on the extensible type whose plugins are measured.
+
+use std::intrinsics::TypeId;
+use std::mem;
+use anymap::AnyMap;
+use Key;
+use raw;
+
+/// A value which can estimate the memory it owns outside itself.
+pub trait SizeHint {
+    /// Returns an estimate of the number of bytes allocated by self on the
+    /// heap, not counting the size of self itself.
+    fn heap_size(&self) -> uint;
+}
+
+/// The key under which the plugins registered for measuring are stored.
+pub struct SizesKey;
+
+impl Key for SizesKey { type Value = Vec<Sizer>; }
+
+bookkeeping!(SizesKey)
+
+/// A plugin registered for measuring.
+pub struct Sizer {
+    id: TypeId,
+    size: fn(&AnyMap) -> uint
+}
+
+/// Registers K in map for measuring, unless it is already registered.
+pub fn register<K: Key>(map: &mut AnyMap) where K::Value: SizeHint {
+    let registered = raw::get_or_insert_with::<SizesKey>(map, Vec::new);
+    let id = TypeId::of::<K>();
+    if !registered.iter().any(|sizer| sizer.id == id) {
+        registered.push(Sizer { id: id, size: size::<K> });
+    }
+}
+
+/// Returns the total estimated size of the registered plugins stored in map.
+pub fn total(map: &AnyMap) -> uint {
+    match raw::find::<SizesKey>(map) {
+        Some(sizers) => sizers.iter().fold(0, |total, sizer| total + (sizer.size)(map)),
+        None => 0
+    }
+}
+
+fn size<K: Key>(map: &AnyMap) -> uint where K::Value: SizeHint {
+    match raw::find::<K>(map) {
+        Some(value) => mem::size_of::<K::Value>() + value.heap_size(),
+        None => 0
+    }
+}
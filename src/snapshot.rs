@@ -0,0 +1,124 @@
+//! Snapshots of stored plugins as bytes, enabled by the `serde` cargo
+//! feature.
+//!
+//! Values are type-erased in an AnyMap, so each plugin which can be
+//! snapshotted must be registered on the extensible type, under a tag which
+//! names it in the snapshot.
+
+use std::intrinsics::TypeId;
+use anymap::AnyMap;
+use Key;
+use raw;
+
+/// A plugin whose value can be written to and read from bytes.
+pub trait SerializablePlugin: Key {
+    /// A tag naming this plugin in snapshots, which must be unique and stay
+    /// the same between runs.
+    fn tag() -> &'static str;
+
+    /// Write value as bytes.
+    fn serialize(value: &Self::Value) -> Vec<u8>;
+
+    /// Read a value from bytes written by serialize, or None if they are
+    /// invalid.
+    fn deserialize(bytes: &[u8]) -> Option<Self::Value>;
+}
+
+/// The key under which the registered plugins of an extensible type are
+/// stored.
+pub struct SnapshotsKey;
+
+impl Key for SnapshotsKey { type Value = Vec<Registered>; }
+
+bookkeeping!(SnapshotsKey)
+
+/// A plugin registered for snapshots.
+pub struct Registered {
+    /// The TypeId of the plugin's key.
+    pub id: TypeId,
+    /// The plugin's tag.
+    pub tag: &'static str,
+    /// Writes the plugin's value from a map as bytes, if it is stored.
+    pub serialize: fn(&AnyMap) -> Option<Vec<u8>>,
+    /// Stores the plugin's value read from bytes in a map, returning false if
+    /// they are invalid.
+    pub deserialize: fn(&mut AnyMap, &[u8]) -> bool
+}
+
+/// Returns the registration of K.
+pub fn registered<K: SerializablePlugin>() -> Registered {
+    Registered {
+        id: TypeId::of::<K>(),
+        tag: <K as SerializablePlugin>::tag(),
+        serialize: serialize::<K>,
+        deserialize: deserialize::<K>
+    }
+}
+
+fn serialize<K: SerializablePlugin>(map: &AnyMap) -> Option<Vec<u8>> {
+    raw::find::<K>(map).map(|value| <K as SerializablePlugin>::serialize(value))
+}
+
+fn deserialize<K: SerializablePlugin>(map: &mut AnyMap, bytes: &[u8]) -> bool {
+    match <K as SerializablePlugin>::deserialize(bytes) {
+        Some(value) => {
+            raw::insert::<K>(map, value);
+            true
+        },
+        None => false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {GetCached, Key, PluginFor};
+    use fixtures::Extended;
+    use super::SerializablePlugin;
+
+    struct Name;
+
+    impl Key for Name { type Value = String; }
+
+    impl PluginFor<Extended> for Name {
+        fn create(_: &Extended) -> Option<String> { Some("plugin".to_string()) }
+    }
+
+    impl SerializablePlugin for Name {
+        fn tag() -> &'static str { "name" }
+
+        fn serialize(value: &String) -> Vec<u8> { value.clone().into_bytes() }
+
+        fn deserialize(bytes: &[u8]) -> Option<String> {
+            String::from_utf8(bytes.to_vec()).ok()
+        }
+    }
+
+    struct Unregistered;
+
+    impl Key for Unregistered { type Value = uint; }
+
+    #[test] fn test_snapshot() {
+        let mut extended = Extended::new();
+        extended.register_snapshot::<Name>();
+        assert!(extended.snapshot().is_empty())
+
+        extended.insert::<Name>("restored".to_string());
+        extended.insert::<Unregistered>(1);
+        let snapshot = extended.snapshot();
+        assert_eq!(snapshot, vec![("name".to_string(), b"restored".to_vec())])
+
+        let mut fresh = Extended::new();
+        fresh.register_snapshot::<Name>();
+        assert_eq!(fresh.restore(snapshot), 1)
+        assert_eq!(fresh.get_ref::<Name>().map(|s| s.as_slice()), Some("restored"))
+        assert_eq!(fresh.len(), 1)
+    }
+
+    #[test] fn test_restore_skips_unknown() {
+        let mut extended = Extended::new();
+        extended.register_snapshot::<Name>();
+        let data = vec![("other".to_string(), vec![1]), ("name".to_string(), vec![0xff])];
+        assert_eq!(extended.restore(data), 0)
+        assert!(extended.is_empty())
+    }
+}
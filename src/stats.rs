@@ -0,0 +1,191 @@
+//! Counts of cache hits and misses per plugin, enabled by the `stats` cargo
+//! feature.
+//!
+//! The `profiling` feature, which enables `stats`, also times every call of
+//! create made by the methods of GetCached which store what they create,
+//! counting each attempt of get_ref_retry as one call.
+//!
+//! Stats are kept by clear, so they cover the whole lifetime of the
+//! extensible type.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entries;
+use std::intrinsics::TypeId;
+use Key;
+
+/// The key under which the stats of an extensible type are stored.
+pub struct StatsKey;
+
+impl Key for StatsKey { type Value = PluginStats; }
+
+bookkeeping!(StatsKey)
+
+/// The number of accesses of one plugin which found its value stored, and
+/// which had to call create.
+#[deriving(Clone, PartialEq, Show, Default)]
+pub struct Counts {
+    /// Accesses which found the value stored.
+    pub hits: uint,
+    /// Accesses which called create.
+    pub misses: uint
+}
+
+/// Counts of accesses through the methods of GetCached which store what they
+/// create, for each plugin.
+///
+/// Accesses through get_ref_named, get_rc and get_weak count for the plugin
+/// itself. Those through get_ref_by_key count for the map of its pair of
+/// types, which iter includes but no key can name.
+#[deriving(Default)]
+pub struct PluginStats {
+    counts: HashMap<TypeId, Counts>,
+    #[cfg(feature = "profiling")]
+    create_ns: HashMap<TypeId, u64>
+}
+
+impl PluginStats {
+    /// Returns the counts for the plugin with key K, which are 0 if it was
+    /// never accessed.
+    pub fn counts<K: Key>(&self) -> Counts {
+        self.counts_by_id(TypeId::of::<K>())
+    }
+
+    /// Returns the counts for the plugin whose key has the TypeId id.
+    pub fn counts_by_id(&self, id: TypeId) -> Counts {
+        self.counts.get(&id).map(|c| c.clone()).unwrap_or_default()
+    }
+
+    /// Returns an iterator over the TypeIds of the keys of every accessed
+    /// plugin and their counts, in no particular order.
+    pub fn iter(&self) -> Entries<TypeId, Counts> {
+        self.counts.iter()
+    }
+
+    /// Records an access of the plugin with key K which found its value.
+    pub fn record_hit<K: Key>(&mut self) {
+        self.entry::<K>().hits += 1;
+    }
+
+    /// Records an access of the plugin with key K which called create.
+    pub fn record_miss<K: Key>(&mut self) {
+        self.entry::<K>().misses += 1;
+    }
+
+    /// Returns the number of times create ran for the plugin with key K,
+    /// which is 0 if it was never accessed. Every miss calls create once, so
+    /// this is the count of misses; see GetCached::recompute_count for the
+    /// calls which are not counted.
+    pub fn recompute_count<K: Key>(&self) -> uint {
+        self.counts::<K>().misses
+    }
+
+    /// Returns the total time spent in create for the plugin with key K, in
+    /// nanoseconds. Accesses which found the value stored are not timed.
+    #[cfg(feature = "profiling")]
+    pub fn create_ns<K: Key>(&self) -> u64 {
+        self.create_ns.get(&TypeId::of::<K>()).map_or(0, |ns| *ns)
+    }
+
+    /// Records that create for the plugin with key K took ns nanoseconds.
+    #[cfg(feature = "profiling")]
+    pub fn record_create<K: Key>(&mut self, ns: u64) {
+        let id = TypeId::of::<K>();
+        let total = self.create_ns.get(&id).map_or(0, |total| *total) + ns;
+        self.create_ns.insert(id, total);
+    }
+
+    fn entry<K: Key>(&mut self) -> &mut Counts {
+        let id = TypeId::of::<K>();
+        if !self.counts.contains_key(&id) {
+            self.counts.insert(id, Default::default());
+        }
+        self.counts.get_mut(&id).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "profiling")] use std::io::timer;
+    #[cfg(feature = "profiling")] use std::time::Duration;
+    #[cfg(feature = "profiling")] use {Key, PluginFor};
+    use GetCached;
+    use fixtures::{Extended, Missing, One};
+    use super::Counts;
+
+    #[test] fn test_counts() {
+        let mut extended = Extended::new();
+        assert!(extended.plugin_stats().is_none())
+
+        extended.get_ref::<One>();
+        extended.get_ref::<One>();
+        extended.get::<One>();
+        extended.get_mut::<Missing>();
+        extended.get_mut::<Missing>();
+
+        let stats = extended.plugin_stats().unwrap();
+        assert_eq!(stats.counts::<One>(), Counts { hits: 2, misses: 1 })
+        assert_eq!(stats.counts::<Missing>(), Counts { hits: 0, misses: 2 })
+        assert_eq!(stats.iter().count(), 2)
+    }
+
+    #[test] fn test_clear_keeps_stats() {
+        let mut extended = Extended::new();
+        extended.get_ref::<One>();
+        extended.clear();
+        assert!(extended.is_empty())
+        extended.get_ref::<One>();
+        assert_eq!(extended.plugin_stats().unwrap().counts::<One>(), Counts { hits: 0, misses: 2 })
+    }
+
+    #[test] fn test_recompute_count() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.recompute_count::<One>(), 0)
+        extended.get_ref::<One>();
+        extended.get_ref::<One>();
+        assert_eq!(extended.recompute_count::<One>(), 1)
+        extended.clear();
+        extended.get_ref::<One>();
+        extended.get_mut::<Missing>();
+        extended.get_mut::<Missing>();
+        assert_eq!(extended.recompute_count::<One>(), 2)
+        assert_eq!(extended.recompute_count::<Missing>(), 2)
+
+        extended.get_ref_retry::<Missing>(3);
+        extended.get_rc::<One>();
+        extended.get_rc::<One>();
+        extended.compute_uncached::<One>();
+        assert_eq!(extended.recompute_count::<One>(), 3)
+        assert_eq!(extended.recompute_count::<Missing>(), 5)
+    }
+
+    #[cfg(feature = "profiling")]
+    #[test] fn test_create_ns() {
+        let mut extended = Extended::new();
+        extended.get_ref::<One>();
+        let first = extended.plugin_stats().unwrap().create_ns::<One>();
+        extended.get_ref::<One>();
+        assert_eq!(extended.plugin_stats().unwrap().create_ns::<One>(), first)
+        assert_eq!(extended.plugin_stats().unwrap().create_ns::<Missing>(), 0)
+    }
+
+    #[cfg(feature = "profiling")]
+    struct Slow;
+
+    #[cfg(feature = "profiling")]
+    impl Key for Slow { type Value = uint; }
+
+    #[cfg(feature = "profiling")]
+    impl PluginFor<Extended> for Slow {
+        fn create(_: &Extended) -> Option<uint> {
+            timer::sleep(Duration::milliseconds(1));
+            None
+        }
+    }
+
+    #[cfg(feature = "profiling")]
+    #[test] fn test_create_ns_retry() {
+        let mut extended = Extended::new();
+        assert_eq!(extended.get_ref_retry::<Slow>(3), None)
+        assert!(extended.plugin_stats().unwrap().create_ns::<Slow>() >= 3_000_000)
+    }
+}
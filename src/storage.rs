@@ -0,0 +1,204 @@
+//! Type-erased maps from keys to plugin values.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::intrinsics::TypeId;
+use anymap::AnyMap;
+use {Key, PluginFor};
+use raw;
+
+/// The type-erased map an AnyMap is built on.
+pub type RawMap = HashMap<TypeId, Box<Any + 'static>>;
+
+/// A map storing at most one value under each key, of its value type.
+///
+/// This is implemented for AnyMap, and for the plain HashMap it is built on,
+/// storing values under the TypeIds of their keys in both. Methods must be
+/// called as `Storage::find::<K>(&map)`, since AnyMap and HashMap have
+/// inherent methods of the same names.
+pub trait Storage {
+    /// Returns a reference to the value stored under K, if any.
+    fn find<K: Key>(&self) -> Option<&K::Value>;
+
+    /// Returns a mutable reference to the value stored under K, if any.
+    fn find_mut<K: Key>(&mut self) -> Option<&mut K::Value>;
+
+    /// Stores value under K, returning the value previously stored under K.
+    fn insert<K: Key>(&mut self, value: K::Value) -> Option<K::Value>;
+
+    /// Returns true if a value is stored under K.
+    fn contains<K: Key>(&self) -> bool;
+
+    /// Removes and returns the value stored under K, if any.
+    fn remove<K: Key>(&mut self) -> Option<K::Value>;
+}
+
+impl Storage for RawMap {
+    fn find<K: Key>(&self) -> Option<&K::Value> {
+        self.get(&TypeId::of::<K>()).and_then(|value| raw::value_ref::<K>(value))
+    }
+
+    fn find_mut<K: Key>(&mut self) -> Option<&mut K::Value> {
+        self.get_mut(&TypeId::of::<K>()).and_then(|value| raw::value_mut::<K>(value))
+    }
+
+    fn insert<K: Key>(&mut self, value: K::Value) -> Option<K::Value> {
+        self.insert(TypeId::of::<K>(), box value as Box<Any + 'static>)
+            .and_then(|old| raw::value::<K>(old))
+    }
+
+    fn contains<K: Key>(&self) -> bool {
+        self.contains_key(&TypeId::of::<K>())
+    }
+
+    fn remove<K: Key>(&mut self) -> Option<K::Value> {
+        self.remove(&TypeId::of::<K>()).and_then(|old| raw::value::<K>(old))
+    }
+}
+
+impl Storage for AnyMap {
+    fn find<K: Key>(&self) -> Option<&K::Value> {
+        Storage::find::<K>(self.as_raw())
+    }
+
+    fn find_mut<K: Key>(&mut self) -> Option<&mut K::Value> {
+        Storage::find_mut::<K>(self.as_raw_mut())
+    }
+
+    fn insert<K: Key>(&mut self, value: K::Value) -> Option<K::Value> {
+        Storage::insert::<K>(self.as_raw_mut(), value)
+    }
+
+    fn contains<K: Key>(&self) -> bool {
+        Storage::contains::<K>(self.as_raw())
+    }
+
+    fn remove<K: Key>(&mut self) -> Option<K::Value> {
+        Storage::remove::<K>(self.as_raw_mut())
+    }
+}
+
+/// An interface for types whose plugins are stored in a map of their own
+/// choosing, such as a plain RawMap, rather than in the AnyMap of
+/// Extensible.
+pub trait MapExtensible {
+    /// The type of the map plugins are stored in.
+    type Map: Storage;
+
+    /// Get a reference to the type's plugin storage.
+    fn storage(&self) -> &Self::Map;
+
+    /// Get a mutable reference to the type's plugin storage.
+    fn storage_mut(&mut self) -> &mut Self::Map;
+}
+
+/// Expose an interface for cacheing plugins of MapExtensible types.
+///
+/// Values are created and stored as by GetCached, but the map holds nothing
+/// else, so there is none of its bookkeeping: no generations, stats,
+/// capacity, observer, or frozen and poisoned state.
+pub trait GetCachedMap: MapExtensible {
+    /// Creates, stores and returns reference of T's value if construction
+    /// through T's implementation of create succeeds, otherwise None.
+    fn get_ref<T: PluginFor<Self>>(&mut self) -> Option<&T::Value> {
+        if !Storage::contains::<T>(self.storage()) {
+            let t = try_option!(<T as PluginFor<Self>>::create(self));
+            Storage::insert::<T>(self.storage_mut(), t);
+        }
+        Storage::find::<T>(self.storage())
+    }
+
+    /// Creates, stores and returns a mutable ref of T's value as get_ref
+    /// does.
+    fn get_mut<T: PluginFor<Self>>(&mut self) -> Option<&mut T::Value> {
+        if !Storage::contains::<T>(self.storage()) {
+            let t = try_option!(<T as PluginFor<Self>>::create(self));
+            Storage::insert::<T>(self.storage_mut(), t);
+        }
+        Storage::find_mut::<T>(self.storage_mut())
+    }
+
+    /// Creates, stores and returns a clone of T's value as get_ref does.
+    fn get<T: PluginFor<Self>>(&mut self) -> Option<T::Value> where T::Value: Clone {
+        self.get_ref::<T>().map(|t| t.clone())
+    }
+
+    /// Stores value under T without calling create, returning the value
+    /// previously stored under T, if any.
+    fn insert<T: Key>(&mut self, value: T::Value) -> Option<T::Value> {
+        Storage::insert::<T>(self.storage_mut(), value)
+    }
+
+    /// Removes and returns the value stored under T, if any.
+    fn remove<T: Key>(&mut self) -> Option<T::Value> {
+        Storage::remove::<T>(self.storage_mut())
+    }
+
+    /// Returns true if a value is stored under T. This never calls create.
+    fn contains<T: Key>(&self) -> bool {
+        Storage::contains::<T>(self.storage())
+    }
+}
+
+impl<T: MapExtensible> GetCachedMap for T {}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use anymap::AnyMap;
+    use {Key, PluginFor};
+    use super::{GetCachedMap, MapExtensible, RawMap, Storage};
+
+    #[deriving(PartialEq, Show)]
+    struct One(uint);
+
+    impl Key for One { type Value = One; }
+
+    #[test] fn test_raw_map() {
+        let mut map: RawMap = HashMap::new();
+        assert_eq!(Storage::insert::<One>(&mut map, One(1)), None)
+        assert!(Storage::contains::<One>(&map))
+        *Storage::find_mut::<One>(&mut map).unwrap() = One(2);
+        assert_eq!(Storage::find::<One>(&map), Some(&One(2)))
+        assert_eq!(Storage::remove::<One>(&mut map), Some(One(2)))
+        assert!(map.is_empty())
+    }
+
+    #[test] fn test_anymap() {
+        let mut map = AnyMap::new();
+        Storage::insert::<One>(&mut map, One(1));
+        assert_eq!(map.find::<One>(), Some(&One(1)))
+    }
+
+    struct Counter {
+        n: uint,
+        map: RawMap
+    }
+
+    impl MapExtensible for Counter {
+        type Map = RawMap;
+
+        fn storage(&self) -> &RawMap { &self.map }
+        fn storage_mut(&mut self) -> &mut RawMap { &mut self.map }
+    }
+
+    struct Tripled;
+
+    impl Key for Tripled { type Value = uint; }
+
+    impl PluginFor<Counter> for Tripled {
+        fn create(counter: &Counter) -> Option<uint> { Some(counter.n * 3) }
+    }
+
+    #[test] fn test_get_cached_map() {
+        let mut counter = Counter { n: 2, map: HashMap::new() };
+        assert_eq!(counter.get_ref::<Tripled>(), Some(&6))
+        counter.n = 3;
+        assert_eq!(counter.get::<Tripled>(), Some(6))
+        assert!(counter.contains::<Tripled>())
+        assert_eq!(counter.map.len(), 1)
+
+        assert_eq!(counter.remove::<Tripled>(), Some(6))
+        assert_eq!(counter.get_mut::<Tripled>(), Some(&mut 9))
+    }
+}
@@ -0,0 +1,182 @@
+//! Plugins for extensible types shared between tasks.
+
+use std::sync::{RWLock, RWLockReadGuard};
+use anymap::AnyMap;
+use super::{Key, PluginFor};
+use raw;
+
+/// A plugin whose value can be safely moved and shared between tasks.
+///
+/// This is implemented for every plugin whose value is Send and Sync.
+pub trait SendPluginFor<T>: PluginFor<T> {}
+
+impl<T, P> SendPluginFor<T> for P where P: PluginFor<T>, P::Value: Send + Sync {}
+
+/// Expose an interface for cacheing plugins on types which can be shared
+/// between tasks, which only accepts plugins that can be too.
+///
+/// This is not implemented for Extensible types: AnyMap is never Send, and
+/// an unsafe impl of Send for a type holding one would let GetCached store
+/// values which are not. It is implemented for SyncExtensible, whose storage
+/// only accepts Send and Sync values.
+pub trait GetSend: Send + Sync {
+    /// Creates, stores and returns an instance of T's value, for plugins
+    /// whose value is Send and Sync.
+    fn get_send<T>(&self) -> Option<T::Value> where T: SendPluginFor<Self>, T::Value: Clone;
+
+    /// Creates and stores T's value, then returns a guard which derefs to
+    /// it, for plugins whose value is Send and Sync.
+    fn get_ref_send<'a, T: SendPluginFor<Self>>(&'a self) -> Option<SyncRef<'a, T>>;
+}
+
+impl GetSend for SyncExtensible {
+    fn get_send<T>(&self) -> Option<T::Value>
+    where T: SendPluginFor<SyncExtensible>, T::Value: Clone {
+        self.get::<T>()
+    }
+
+    fn get_ref_send<'a, T: SendPluginFor<SyncExtensible>>(&'a self) -> Option<SyncRef<'a, T>> {
+        self.get_ref::<T>()
+    }
+}
+
+/// An extensible type whose plugins can be created and read through a shared
+/// reference from multiple tasks.
+///
+/// Only plugins whose values are both Send and Sync can be stored.
+pub struct SyncExtensible {
+    map: RWLock<AnyMap>
+}
+
+// Every value in the map is Send + Sync, as the map is only reachable
+// through methods which require it.
+unsafe impl Send for SyncExtensible {}
+unsafe impl Sync for SyncExtensible {}
+
+impl SyncExtensible {
+    /// Create a SyncExtensible with no plugins stored.
+    pub fn new() -> SyncExtensible {
+        SyncExtensible { map: RWLock::new(AnyMap::new()) }
+    }
+
+    /// Creates, stores and returns an instance of T's value if construction
+    /// through T's implementation of create succeeds, otherwise None.
+    ///
+    /// No lock is held while create runs, so create may itself get other
+    /// plugins. Two tasks which both find T missing may both call create, in
+    /// which case the value stored first is kept and returned to both.
+    pub fn get<T>(&self) -> Option<T::Value>
+    where T: SendPluginFor<SyncExtensible>, T::Value: Clone {
+        match raw::find::<T>(&*self.map.read()) {
+            Some(t) => return Some(t.clone()),
+            None => ()
+        }
+        let t = try_option!(<T as PluginFor<SyncExtensible>>::create(self));
+        let mut map = self.map.write();
+        if !raw::contains::<T>(&*map) {
+            raw::insert::<T>(&mut *map, t);
+        }
+        raw::find::<T>(&*map).map(|t| t.clone())
+    }
+
+    /// Creates and stores T's value as get does, then returns a guard which
+    /// holds a read lock on the stored plugins, and derefs to the value.
+    ///
+    /// While any guard is alive, every attempt to store a plugin blocks,
+    /// so calling get or get_ref for a missing plugin while holding a guard
+    /// on the same task deadlocks.
+    pub fn get_ref<'a, T>(&'a self) -> Option<SyncRef<'a, T>>
+    where T: SendPluginFor<SyncExtensible> {
+        if !raw::contains::<T>(&*self.map.read()) {
+            let t = try_option!(<T as PluginFor<SyncExtensible>>::create(self));
+            let mut map = self.map.write();
+            if !raw::contains::<T>(&*map) {
+                raw::insert::<T>(&mut *map, t);
+            }
+        }
+        Some(SyncRef { guard: self.map.read() })
+    }
+
+    /// Stores value under T without calling create, returning the value
+    /// previously stored under T, if any.
+    pub fn insert<T: Key>(&self, value: T::Value) -> Option<T::Value>
+    where T::Value: Send + Sync {
+        raw::insert::<T>(&mut *self.map.write(), value)
+    }
+
+    /// Returns true if a value is currently stored under T.
+    pub fn contains<T: Key>(&self) -> bool {
+        raw::contains::<T>(&*self.map.read())
+    }
+}
+
+/// A read lock on the plugins of a SyncExtensible, which derefs to the value
+/// stored under T.
+pub struct SyncRef<'a, T> {
+    guard: RWLockReadGuard<'a, AnyMap>
+}
+
+impl<'a, T: Key> Deref<T::Value> for SyncRef<'a, T> {
+    fn deref<'b>(&'b self) -> &'b T::Value {
+        // Stores are blocked while the guard is alive, so T is still present.
+        raw::find::<T>(&*self.guard).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SyncExtensible, GetSend, SendPluginFor};
+    use {Key, PluginFor};
+
+    #[deriving(PartialEq, Show, Clone)]
+    struct One(uint);
+
+    impl Key for One { type Value = One; }
+
+    impl PluginFor<SyncExtensible> for One {
+        fn create(_: &SyncExtensible) -> Option<One> { Some(One(1)) }
+    }
+
+    #[deriving(PartialEq, Show, Clone)]
+    struct Two(uint);
+
+    impl Key for Two { type Value = Two; }
+
+    // Gets another plugin during creation.
+    impl PluginFor<SyncExtensible> for Two {
+        fn create(extensible: &SyncExtensible) -> Option<Two> {
+            extensible.get::<One>().map(|One(n)| Two(n * 2))
+        }
+    }
+
+    #[test] fn test_get() {
+        let extensible = SyncExtensible::new();
+        assert_eq!(extensible.get::<One>(), Some(One(1)))
+        assert!(extensible.contains::<One>())
+        extensible.insert::<One>(One(5));
+        assert_eq!(extensible.get::<One>(), Some(One(5)))
+    }
+
+    #[test] fn test_get_reentrant() {
+        let extensible = SyncExtensible::new();
+        assert_eq!(extensible.get::<Two>(), Some(Two(2)))
+        assert!(extensible.contains::<One>())
+    }
+
+    #[test] fn test_get_ref() {
+        let extensible = SyncExtensible::new();
+        let one = extensible.get_ref::<One>().unwrap();
+        assert_eq!(*one, One(1))
+    }
+
+    // Generic over any storage which can be shared between tasks.
+    fn get_shared<E: GetSend>(shared: &E) -> Option<One> where One: SendPluginFor<E> {
+        shared.get_send::<One>()
+    }
+
+    #[test] fn test_get_send() {
+        let extensible = SyncExtensible::new();
+        assert_eq!(get_shared(&extensible), Some(One(1)))
+        assert_eq!(*extensible.get_ref_send::<Two>().unwrap(), Two(2))
+    }
+}
@@ -0,0 +1,78 @@
+//! Helpers for tests of plugins, enabled by the `test-util` cargo feature.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::intrinsics::TypeId;
+use {Extensible, Key, PluginFor};
+use raw;
+use versioned;
+
+/// Panics unless a fresh value is stored under K in extensible.
+pub fn assert_cached<K: Key, E: Extensible>(extensible: &E) {
+    if !versioned::is_fresh::<K, E>(extensible) {
+        panic!("expected {} to be cached in {}", raw::type_name::<K>(), raw::type_name::<E>());
+    }
+}
+
+/// Panics if a fresh value is stored under K in extensible.
+pub fn assert_not_cached<K: Key, E: Extensible>(extensible: &E) {
+    if versioned::is_fresh::<K, E>(extensible) {
+        panic!("expected {} not to be cached in {}", raw::type_name::<K>(), raw::type_name::<E>());
+    }
+}
+
+thread_local!(static CREATES: RefCell<HashMap<TypeId, uint>> = RefCell::new(HashMap::new()))
+
+/// A plugin which creates the value of P, counting how many times it does
+/// so on each thread, as returned by creates.
+///
+/// Its value is stored separately from the value of P.
+pub struct Counting<P>;
+
+impl<P: Key> Key for Counting<P> { type Value = P::Value; }
+
+impl<T, P: PluginFor<T>> PluginFor<T> for Counting<P> {
+    fn create(source: &T) -> Option<P::Value> {
+        CREATES.with(|creates| {
+            let mut creates = creates.borrow_mut();
+            let count = creates.get(&TypeId::of::<P>()).map_or(0, |n| *n) + 1;
+            creates.insert(TypeId::of::<P>(), count);
+        });
+        <P as PluginFor<T>>::create(source)
+    }
+}
+
+/// Returns the number of times Counting<P> has created a value on this
+/// thread, whether or not creation succeeded.
+pub fn creates<P: Key>() -> uint {
+    CREATES.with(|creates| creates.borrow().get(&TypeId::of::<P>()).map_or(0, |n| *n))
+}
+
+/// Resets the number of times Counting<P> has created a value on this thread
+/// to 0.
+pub fn reset_creates<P: Key>() {
+    CREATES.with(|creates| { creates.borrow_mut().remove(&TypeId::of::<P>()); })
+}
+
+#[cfg(test)]
+mod test {
+    use GetCached;
+    use fixtures::{Extended, One};
+    use super::{Counting, assert_cached, assert_not_cached, creates, reset_creates};
+
+    #[test] fn test_counting() {
+        let mut extended = Extended::new();
+        reset_creates::<One>();
+        assert_not_cached::<Counting<One>, _>(&extended);
+        for _ in range(0u, 5) {
+            assert_eq!(extended.get_ref::<Counting<One>>(), Some(&1))
+        }
+        assert_cached::<Counting<One>, _>(&extended);
+        assert_not_cached::<One, _>(&extended);
+        assert_eq!(creates::<One>(), 1)
+    }
+
+    #[test] #[should_fail] fn test_assert_cached() {
+        assert_cached::<One, _>(&Extended::new());
+    }
+}
@@ -0,0 +1,37 @@
+//! A cache of plugin values shared by every source on a thread, used by
+//! compute_thread_cached.
+//!
+//! Values are keyed by their plugin alone, not by the source they were
+//! created from, and live until the thread exits or clear_thread_cache is
+//! called on it.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use anymap::AnyMap;
+use {Key, RcKey};
+use raw;
+
+thread_local!(static CACHE: RefCell<AnyMap> = RefCell::new(AnyMap::new()))
+
+/// Returns the value of K cached on this thread, if any.
+pub fn find<K: Key>() -> Option<Rc<K::Value>> {
+    CACHE.with(|cache| raw::find::<RcKey<K>>(&*cache.borrow()).map(|rc| rc.clone()))
+}
+
+/// Caches value for K on this thread, unless a value was cached while it was
+/// being created, and returns the cached value.
+pub fn insert<K: Key>(value: Rc<K::Value>) -> Rc<K::Value> {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if !raw::contains::<RcKey<K>>(&*cache) {
+            raw::insert::<RcKey<K>>(&mut *cache, value);
+        }
+        raw::find::<RcKey<K>>(&*cache).unwrap().clone()
+    })
+}
+
+/// Removes every value cached on this thread by compute_thread_cached, so
+/// each is created again on its next computation.
+pub fn clear() {
+    CACHE.with(|cache| cache.borrow_mut().clear())
+}
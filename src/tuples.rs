@@ -0,0 +1,28 @@
+//! Plugins made of tuples of plugins.
+//!
+//! A tuple of plugins is a plugin whose value is the tuple of their values,
+//! created only if each of them can be created. It is stored as an entry of
+//! its own: getting (A, B) neither stores nor finds the values of A and B
+//! alone, so each is created again when it is got by itself.
+
+use {Key, PluginFor};
+
+macro_rules! impl_tuple_plugin (
+    ($($name:ident),+) => {
+        impl<$($name: Key),+> Key for ($($name,)+) {
+            type Value = ($($name::Value,)+);
+        }
+
+        impl<T, $($name: PluginFor<T>),+> PluginFor<T> for ($($name,)+) {
+            fn create(source: &T) -> Option<($($name::Value,)+)> {
+                Some(($(try_option!(<$name as PluginFor<T>>::create(source)),)+))
+            }
+        }
+    }
+)
+
+impl_tuple_plugin!(A, B)
+impl_tuple_plugin!(A, B, C)
+impl_tuple_plugin!(A, B, C, D)
+impl_tuple_plugin!(A, B, C, D, E)
+impl_tuple_plugin!(A, B, C, D, E, F)
@@ -0,0 +1,87 @@
+//! Invalidation of stored plugins when the generation of their extensible
+//! type advances, or its content hash changes.
+//!
+//! The generation a plugin was stored at is only recorded when it is not 0,
+//! and its content hash only when the type has one, each as a separate
+//! extension entry holding one u64 per plugin, so types which use neither
+//! pay only for a call to generation and one to content_hash.
+
+use std::collections::HashMap;
+use std::intrinsics::TypeId;
+use {Extensible, Key};
+use {names, raw};
+
+/// The generation each plugin was stored at, by the TypeId of its key.
+struct Generations;
+
+impl Key for Generations { type Value = HashMap<TypeId, u64>; }
+
+/// The content hash each plugin was stored at, by the TypeId of its key.
+struct Hashes;
+
+impl Key for Hashes { type Value = HashMap<TypeId, u64>; }
+
+bookkeeping!(Generations, Hashes)
+
+/// Returns true if a value is stored under K, and was stored at the current
+/// generation and content hash of extensible.
+pub fn is_fresh<K: Key, E: Extensible>(extensible: &E) -> bool {
+    is_fresh_id(extensible, TypeId::of::<K>())
+}
+
+/// Returns true if a value is stored under the key with TypeId id, and was
+/// stored at the current generation and content hash of extensible.
+pub fn is_fresh_id<E: Extensible>(extensible: &E, id: TypeId) -> bool {
+    if !extensible.extensions().as_raw().contains_key(&id) {
+        return false;
+    }
+    let generation = extensible.generation();
+    let map = extensible.extensions();
+    if generation != 0 && !stored_at(raw::find::<Generations>(map), id, generation) {
+        return false;
+    }
+    match extensible.content_hash() {
+        Some(hash) => stored_at(raw::find::<Hashes>(map), id, hash),
+        None => true
+    }
+}
+
+fn stored_at(values: Option<&HashMap<TypeId, u64>>, id: TypeId, value: u64) -> bool {
+    values.and_then(|values| values.get(&id)) == Some(&value)
+}
+
+/// Stores value under K at the current generation of extensible, returning
+/// the value previously stored under K. The type name of K is recorded for
+/// debug_plugins.
+pub fn insert<K: Key, E: Extensible>(extensible: &mut E, value: K::Value) -> Option<K::Value> {
+    record(extensible, TypeId::of::<K>());
+    names::record::<K>(extensible.extensions_mut());
+    raw::insert::<K>(extensible.extensions_mut(), value)
+}
+
+/// Records that the value stored under the key with TypeId id was stored at
+/// the current generation and content hash of extensible.
+pub fn record<E: Extensible>(extensible: &mut E, id: TypeId) {
+    ordered(extensible, id);
+    let generation = extensible.generation();
+    if generation != 0 {
+        raw::get_or_insert_with::<Generations>(extensible.extensions_mut(), HashMap::new)
+            .insert(id, generation);
+    }
+    match extensible.content_hash() {
+        Some(hash) => {
+            raw::get_or_insert_with::<Hashes>(extensible.extensions_mut(), HashMap::new)
+                .insert(id, hash);
+        },
+        None => ()
+    }
+}
+
+#[cfg(feature = "ordered")]
+fn ordered<E: Extensible>(extensible: &mut E, id: TypeId) {
+    ::order::record(extensible.extensions_mut(), id);
+}
+
+#[cfg(not(feature = "ordered"))]
+#[inline(always)]
+fn ordered<E: Extensible>(_: &mut E, _: TypeId) {}
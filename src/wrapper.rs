@@ -0,0 +1,65 @@
+//! A wrapper making any type extensible.
+
+use anymap::AnyMap;
+use Extensible;
+
+/// A value of T with extension storage of its own, so plugins can be cached
+/// for types which are not extensible themselves, such as those of other
+/// crates.
+///
+/// It derefs to the wrapped value. Plugins are implemented for Extended<T>,
+/// and can reach the value through deref. Methods of GetCached, such as len,
+/// are found before methods of T of the same name, which must be called on
+/// the deref'd value.
+pub struct Extended<T> {
+    value: T,
+    map: AnyMap
+}
+
+impl<T> Extended<T> {
+    /// Wrap value, with no plugins stored.
+    pub fn new(value: T) -> Extended<T> {
+        Extended { value: value, map: AnyMap::new() }
+    }
+
+    /// Unwrap the value, dropping every stored plugin.
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T> Extensible for Extended<T> {
+    fn extensions(&self) -> &AnyMap { &self.map }
+    fn extensions_mut(&mut self) -> &mut AnyMap { &mut self.map }
+}
+
+impl<T> Deref<T> for Extended<T> {
+    fn deref<'a>(&'a self) -> &'a T { &self.value }
+}
+
+impl<T> DerefMut<T> for Extended<T> {
+    fn deref_mut<'a>(&'a mut self) -> &'a mut T { &mut self.value }
+}
+
+#[cfg(test)]
+mod test {
+    use {GetCached, Key, PluginFor};
+    use super::Extended;
+
+    struct Length;
+
+    impl Key for Length { type Value = uint; }
+
+    impl PluginFor<Extended<String>> for Length {
+        fn create(text: &Extended<String>) -> Option<uint> { Some((**text).len()) }
+    }
+
+    #[test] fn test_extended() {
+        let mut text = Extended::new("text".to_string());
+        assert_eq!(text.get_ref::<Length>(), Some(&4))
+        text.push_str("s");
+        assert_eq!(text.as_slice(), "texts")
+        assert_eq!(text.get_ref::<Length>(), Some(&4))
+        assert_eq!(text.into_inner(), "texts".to_string())
+    }
+}